@@ -0,0 +1,157 @@
+// ggl --- global git log
+// Copyright (C) 2022  Honza Pokorny <honza@pokorny.ca>
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Credentials for fetching from private remotes.
+//!
+//! libgit2 keeps calling the credentials callback for as long as
+//! authentication fails, so we keep track of what we've already offered and
+//! move on to the next option each time:
+//!
+//! 1.  For SSH, the keys in ssh-agent, then `~/.ssh/id_ed25519`,
+//!     `~/.ssh/id_ecdsa` and `~/.ssh/id_rsa`
+//! 2.  For HTTPS, the configured git credential helper, then a token from the
+//!     `GGL_TOKEN` environment variable
+//! 3.  Whatever the platform offers by default, e.g. NTLM or Kerberos
+//!
+//! An [`Auth`] from the config can replace the SSH keys, the username and the
+//! token variable; a configured token is tried before the credential helper.
+
+use crate::{expand_path, Auth};
+use indicatif::ProgressBar;
+use std::collections::VecDeque;
+use std::env;
+use std::path::PathBuf;
+
+const SSH_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+const TOKEN_VAR: &str = "GGL_TOKEN";
+
+struct Credentials {
+    config: Option<git2::Config>,
+    username: Option<String>,
+    token_var: Option<String>,
+    tried_agent: bool,
+    ssh_keys: VecDeque<PathBuf>,
+    tried_helper: bool,
+    tried_token: bool,
+    tried_default: bool,
+}
+
+impl Credentials {
+    fn new(config: Option<git2::Config>, auth: &Auth) -> Credentials {
+        let ssh_keys = match (&auth.ssh_key, dirs::home_dir()) {
+            (Some(key), _) => VecDeque::from([PathBuf::from(expand_path(key))]),
+            (None, Some(home)) => SSH_KEYS
+                .iter()
+                .map(|key| home.join(".ssh").join(key))
+                .filter(|path| path.exists())
+                .collect(),
+            (None, None) => VecDeque::new(),
+        };
+
+        Credentials {
+            config,
+            username: auth.username.clone(),
+            token_var: auth.token_env.clone(),
+            // A key from the config means the agent's keys are the wrong ones
+            tried_agent: auth.ssh_key.is_some(),
+            ssh_keys,
+            tried_helper: false,
+            tried_token: false,
+            tried_default: false,
+        }
+    }
+
+    fn next(
+        &mut self,
+        url: &str,
+        username: Option<&str>,
+        allowed: git2::CredentialType,
+    ) -> Result<git2::Cred, git2::Error> {
+        let username = self.username.as_deref().or(username);
+        let user = username.unwrap_or("git");
+
+        if allowed.contains(git2::CredentialType::USERNAME) {
+            return git2::Cred::username(user);
+        }
+
+        if allowed.contains(git2::CredentialType::SSH_KEY) {
+            if !self.tried_agent {
+                self.tried_agent = true;
+                return git2::Cred::ssh_key_from_agent(user);
+            }
+            if let Some(key) = self.ssh_keys.pop_front() {
+                return git2::Cred::ssh_key(user, None, &key, None);
+            }
+        }
+
+        if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            if !self.tried_token && self.token_var.is_some() {
+                self.tried_token = true;
+                if let Ok(token) = env::var(self.token_var.as_deref().unwrap_or(TOKEN_VAR)) {
+                    return git2::Cred::userpass_plaintext(user, &token);
+                }
+            }
+            if !self.tried_helper {
+                self.tried_helper = true;
+                if let Some(config) = &self.config {
+                    if let Ok(cred) = git2::Cred::credential_helper(config, url, username) {
+                        return Ok(cred);
+                    }
+                }
+            }
+            if !self.tried_token {
+                self.tried_token = true;
+                if let Ok(token) = env::var(TOKEN_VAR) {
+                    return git2::Cred::userpass_plaintext(user, &token);
+                }
+            }
+        }
+
+        if allowed.contains(git2::CredentialType::DEFAULT) && !self.tried_default {
+            self.tried_default = true;
+            return git2::Cred::default();
+        }
+
+        Err(git2::Error::from_str(&format!(
+            "authentication failed for {}",
+            url
+        )))
+    }
+}
+
+/// Fetch options that authenticate with the credentials described above.
+/// `config` is the repository's git config, which is where we look up the
+/// credential helper.  How many objects came in so far goes to `transfer`.
+pub(crate) fn fetch_options<'a>(
+    config: Option<git2::Config>,
+    auth: Option<&Auth>,
+    transfer: Option<ProgressBar>,
+) -> git2::FetchOptions<'a> {
+    let mut credentials = Credentials::new(config, auth.unwrap_or(&Auth::default()));
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| credentials.next(url, username, allowed));
+    if let Some(bar) = transfer {
+        callbacks.transfer_progress(move |stats| {
+            bar.set_length(stats.total_objects() as u64);
+            bar.set_position(stats.received_objects() as u64);
+            true
+        });
+    }
+
+    let mut options = git2::FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    options
+}
@@ -0,0 +1,185 @@
+// ggl --- global git log
+// Copyright (C) 2022  Honza Pokorny <honza@pokorny.ca>
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `--format html`: the log as a single HTML page, with its styles and
+//! script inline, so that it can be attached to an email or put on any web
+//! server as it is.
+
+use crate::{dates, name_hash, Group};
+use ggl::{CommitSet, GlobalCommit};
+use std::fmt::Write;
+
+static STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+input { font-size: 1em; padding: 0.3em; width: 100%; box-sizing: border-box; }
+ol { list-style: none; padding-left: 0; }
+ol ol { padding-left: 1.5em; }
+li { margin: 0.3em 0; }
+.commit { border-left: 0.4em solid var(--repo); padding-left: 0.6em; }
+.repo { color: var(--repo); font-weight: bold; }
+.sha { font-family: monospace; }
+.meta { color: #777; font-size: 0.9em; }
+summary { cursor: pointer; font-weight: bold; }
+";
+
+// Hide the commits that don't have every word of the filter, and the sets and
+// groups that are left with none
+static SCRIPT: &str = "
+document.getElementById('filter').addEventListener('input', function (e) {
+  var words = e.target.value.toLowerCase().split(/\\s+/).filter(Boolean);
+  document.querySelectorAll('.commit').forEach(function (c) {
+    var text = c.dataset.search;
+    c.hidden = !words.every(function (w) { return text.includes(w); });
+  });
+  document.querySelectorAll('.set, section').forEach(function (s) {
+    s.hidden = !s.querySelector('.commit:not([hidden])');
+  });
+});
+";
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// The same repository gets the same color in every report
+fn repo_hue(name: &str) -> u32 {
+    name_hash(name) % 360
+}
+
+fn subject(commit: &GlobalCommit) -> &str {
+    commit.message.lines().next().unwrap_or_default()
+}
+
+fn write_commit(html: &mut String, commit: &GlobalCommit) {
+    let date = time::format_description::parse("[year]-[month]-[day] [hour]:[minute]").unwrap();
+    let when = dates().in_zone(&commit.date);
+    let search = [
+        &commit.repo_name,
+        &commit.author,
+        &commit.author_email,
+        &commit.sha,
+        &commit.message,
+    ]
+    .iter()
+    .flat_map(|text| text.split_whitespace())
+    .collect::<Vec<&str>>()
+    .join(" ");
+    let sha = match &commit.url {
+        Some(url) => format!(
+            "<a class=\"sha\" href=\"{}\">{}</a>",
+            escape(url),
+            &commit.short_sha
+        ),
+        None => format!("<span class=\"sha\">{}</span>", &commit.short_sha),
+    };
+    let _ = writeln!(
+        html,
+        "<li class=\"commit\" style=\"--repo: hsl({}, 60%, 40%)\" data-search=\"{}\">\
+         <span class=\"repo\">{}</span> {} {} \
+         <span class=\"meta\">{}, <time datetime=\"{}\">{}</time></span></li>",
+        repo_hue(&commit.repo_name),
+        escape(&search.to_lowercase()),
+        escape(&commit.repo_name),
+        sha,
+        escape(subject(commit)),
+        escape(&commit.author),
+        when.format(&time::format_description::well_known::Rfc3339)
+            .unwrap_or_default(),
+        when.format(&date).unwrap_or_default()
+    );
+}
+
+fn page(body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>Global git log</title>\n\
+         <style>{}</style>\n\
+         </head>\n\
+         <body>\n\
+         <h1>Global git log</h1>\n\
+         <input id=\"filter\" type=\"search\" \
+         placeholder=\"Filter by repository, author, SHA or message\">\n\
+         {}\
+         <script>{}</script>\n\
+         </body>\n\
+         </html>\n",
+        STYLE, body, SCRIPT
+    )
+}
+
+/// The page for `sets`, with each merge and the commits it brought in
+/// collapsible under the merge's subject.
+pub fn render_sets(sets: &[CommitSet]) -> String {
+    let mut body = String::from("<ol>\n");
+    for set in sets {
+        let first = match set.commits.first() {
+            Some(first) => first,
+            None => continue,
+        };
+        let merge = set.commits.iter().find(|commit| commit.merge);
+        // Nothing to collapse, e.g. with --merges
+        if set.commits.len() == 1 {
+            write_commit(&mut body, first);
+            continue;
+        }
+
+        let summary = match merge {
+            Some(merge) => subject(merge),
+            None => "Merged",
+        };
+        let _ = writeln!(
+            body,
+            "<li class=\"set\"><details open><summary>{} \
+             <span class=\"meta\">({}, {} commits)</span></summary><ol>",
+            escape(summary),
+            escape(&first.repo_name),
+            set.commits.len()
+        );
+        for commit in &set.commits {
+            write_commit(&mut body, commit);
+        }
+        body.push_str("</ol></details></li>\n");
+    }
+    body.push_str("</ol>\n");
+    page(&body)
+}
+
+/// The page for `groups`, each under a heading of its own.
+pub fn render_groups(groups: &[Group]) -> String {
+    let mut body = String::new();
+    for group in groups {
+        let _ = writeln!(body, "<section><h2>{}</h2><ol>", escape(&group.heading()));
+        for commit in &group.commits {
+            write_commit(&mut body, commit);
+        }
+        body.push_str("</ol></section>\n");
+    }
+    page(&body)
+}
@@ -0,0 +1,110 @@
+// ggl --- global git log
+// Copyright (C) 2022  Honza Pokorny <honza@pokorny.ca>
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `--format ics`: the log as an iCalendar file (RFC 5545), with an event for
+//! each commit set, to lay over a calendar.
+
+use ggl::{CommitSet, GlobalCommit};
+use time::macros::format_description;
+use time::{OffsetDateTime, UtcOffset};
+
+// Backslash what iCalendar uses to separate values
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn utc(date: &OffsetDateTime) -> String {
+    date.to_offset(UtcOffset::UTC)
+        .format(format_description!(
+            "[year][month][day]T[hour][minute][second]Z"
+        ))
+        .unwrap_or_default()
+}
+
+fn subject(commit: &GlobalCommit) -> &str {
+    commit.message.lines().next().unwrap_or_default()
+}
+
+// Lines may only be 75 bytes long, and go on after a space on the next one
+fn push_line(ics: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            ics.push_str("\r\n ");
+            length = 1;
+        }
+        ics.push(c);
+        length += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+/// The calendar with an event for each of `sets`, at the set's date, named
+/// after its merge or its only commit, and listing its commits.
+pub fn render(sets: &[CommitSet]) -> String {
+    let mut ics = String::new();
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, "PRODID:-//ggl//global git log//EN");
+
+    let now = utc(&OffsetDateTime::now_utc());
+    for set in sets {
+        let main = match set.commits.iter().find(|commit| commit.merge) {
+            Some(merge) => merge,
+            None => match set.commits.first() {
+                Some(first) => first,
+                // Nothing to put in the calendar
+                None => continue,
+            },
+        };
+        let description = set
+            .commits
+            .iter()
+            .map(|commit| {
+                format!(
+                    "{} {} ({})",
+                    &commit.short_sha,
+                    subject(commit),
+                    commit.author
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        push_line(&mut ics, "BEGIN:VEVENT");
+        push_line(&mut ics, &format!("UID:{}@ggl", main.sha));
+        push_line(&mut ics, &format!("DTSTAMP:{}", now));
+        push_line(&mut ics, &format!("DTSTART:{}", utc(&set.date)));
+        push_line(
+            &mut ics,
+            &format!(
+                "SUMMARY:{}",
+                escape(&format!("{}: {}", main.repo_name, subject(main)))
+            ),
+        );
+        push_line(&mut ics, &format!("DESCRIPTION:{}", escape(&description)));
+        if let Some(url) = &main.url {
+            push_line(&mut ics, &format!("URL:{}", url));
+        }
+        push_line(&mut ics, "END:VEVENT");
+    }
+
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
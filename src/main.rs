@@ -14,456 +14,2254 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+mod html;
+mod ical;
+#[cfg(feature = "parquet")]
+mod parquet_format;
+
 use colored::*;
-use dirs;
-use git2;
-use serde::{Deserialize, Serialize};
-use std::fs;
+use ggl::{
+    clone_missing, collect_commitsets, detect_upstream, fetch_all, find_git_repositories,
+    get_config_path, get_date_range, load_config_as, plan, validate_config, Block, ChangedFile,
+    CloneOutcome, CloneReport, CollectOptions, CommitSet, Config, ConfigFormat, DateKind,
+    FetchOutcome, FetchReport, Filter, GglError, GlobalCommit, MatchType, Progress, Repository,
+    SignatureStatus, Theme, JSON_SCHEMA,
+};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::str;
+use std::process;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::thread;
 use structopt::StructOpt;
-use time;
-
-// git format: Wed Nov 16 11:05:18 2022 -0400
-static DATETIME: &str = "[weekday repr:short] [month repr:short] \
-                         [day padding:none] [hour]:[minute]:[second] \
-                         [year] [offset_hour sign:mandatory][offset_minute]";
+use textwrap::core::display_width;
+use time::format_description::well_known::Rfc3339;
+use time::format_description::OwnedFormatItem;
+use tracing::warn;
 
 #[derive(StructOpt)]
 struct Args {
+    #[structopt(name = "config", long, short, global = true, number_of_values = 1)]
+    /// Path to config file; may be given more than once to merge several configs
+    config: Vec<PathBuf>,
+
+    #[structopt(name = "config-format", long, global = true)]
+    /// Format of the config file: yaml, toml or json; by default, guessed from the file extension
+    config_format: Option<ConfigFormat>,
+
+    #[structopt(name = "profile", long, short = "P", global = true)]
+    /// Only use the repositories in this profile from the config
+    profile: Option<String>,
+
+    #[structopt(name = "block", long, global = true, number_of_values = 1)]
+    /// Only use the block with this name; may be given more than once
+    block: Vec<String>,
+
+    #[structopt(name = "tag", long, global = true, number_of_values = 1)]
+    /// Only use repositories with this tag; may be given more than once
+    tag: Vec<String>,
+
+    #[structopt(name = "repo", long, global = true, number_of_values = 1)]
+    /// Only use repositories with this name; may be a glob, and may be given more than once
+    repo: Vec<String>,
+
+    #[structopt(name = "fetch-timeout", long, global = true)]
+    /// Give up on fetches that take longer than this many seconds, unless the config says otherwise
+    fetch_timeout: Option<u64>,
+
+    #[structopt(name = "fetch-retries", long, global = true)]
+    /// Retry failed fetches this many times, unless the config says otherwise
+    fetch_retries: Option<u32>,
+
+    #[structopt(name = "color", long, global = true, default_value = "auto")]
+    /// When to use colors: auto, only when printing to a terminal and NO_COLOR isn't set, always or never
+    color: ColorWhen,
+
+    #[structopt(name = "quiet", long, short, global = true)]
+    /// Don't tell what's going on, e.g. which repositories are being fetched
+    quiet: bool,
+
+    #[structopt(name = "verbose", long, short, global = true, parse(from_occurrences))]
+    /// Log what's going on to stderr: -v for each repository walked and how long each step took, -vv for the refs
+    /// walked and how many commits were looked at, and -vvv for every commit filtered out
+    verbose: u8,
+
+    #[structopt(name = "log-format", long, global = true, default_value = "text")]
+    /// How to write warnings, e.g. about failed fetches, and the log of -v to stderr: text, or json, a JSON object
+    /// per line, which also takes the place of the progress
+    log_format: LogFormat,
+
+    #[structopt(name = "dry-run", long, global = true)]
+    /// Only print which repositories would be fetched, walked or cloned, without doing it
+    dry_run: bool,
+
+    // Running `ggl' without a subcommand is the same as `ggl log'
+    #[structopt(flatten)]
+    log: LogArgs,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+impl Args {
+    // The log's arguments, if it's the log we're showing
+    fn log(&self) -> Option<&LogArgs> {
+        match &self.command {
+            None => Some(&self.log),
+            Some(Command::Log(log)) => Some(log),
+            _ => None,
+        }
+    }
+}
+
+/// When `--color` colors the output.
+#[derive(Clone, Copy)]
+enum ColorWhen {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorWhen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorWhen::Auto),
+            "always" => Ok(ColorWhen::Always),
+            "never" => Ok(ColorWhen::Never),
+            _ => Err(format!(
+                "unknown color setting: {}, expected auto, always or never",
+                s
+            )),
+        }
+    }
+}
+
+/// What `--log-format` writes warnings and the log of `-v` as.
+#[derive(Clone, Copy)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format: {}, expected text or json", s)),
+        }
+    }
+}
+
+// Parsed once, so the size of the log arguments doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(StructOpt)]
+enum Command {
+    /// Show the global git log; this is the default
+    Log(LogArgs),
+
+    /// Scan a directory for git repositories and write a starter config
+    Init(InitArgs),
+
+    /// Check that every repository, remote and branch in the config exists
+    Validate,
+
+    /// List the configured repositories
+    Repos(ReposArgs),
+
+    /// Run git fetch for every repository, without showing the log
+    Fetch(FetchArgs),
+
+    /// Clone the repositories that don't exist yet from their url
+    Clone(CloneArgs),
+
+    /// Print the JSON Schema of the log's JSON output
+    Schema,
+}
+
+#[derive(StructOpt)]
+struct CloneArgs {
+    #[structopt(name = "jobs", long)]
+    /// Number of repositories to clone concurrently; defaults to the number of CPUs
+    jobs: Option<usize>,
+}
+
+#[derive(StructOpt)]
+struct FetchArgs {
+    #[structopt(name = "jobs", long)]
+    /// Number of repositories to fetch concurrently; defaults to the number of CPUs
+    jobs: Option<usize>,
+}
+
+#[derive(StructOpt)]
+struct ReposArgs {
+    #[structopt(name = "json", long, short)]
+    /// Print JSON
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct RepoInfo<'a> {
+    name: &'a str,
+    block: &'a Option<String>,
+    path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: &'a Option<String>,
+    remote: &'a Option<String>,
+    remotes: &'a [String],
+    branch: &'a Option<String>,
+    #[serde(rename = "ref")]
+    git_ref: &'a Option<String>,
+    fetch: bool,
+    prune: bool,
+    fetch_tags: bool,
+    filters: &'a Option<Vec<Filter>>,
+    tags: &'a [String],
+}
+
+#[derive(StructOpt)]
+struct InitArgs {
+    #[structopt(name = "dir", parse(from_os_str))]
+    /// Directory to scan for repositories; defaults to the current directory
+    dir: Option<PathBuf>,
+
+    #[structopt(name = "yes", long, short)]
+    /// Include every repository found without asking
+    yes: bool,
+
+    #[structopt(name = "force", long)]
+    /// Overwrite an existing config file
+    force: bool,
+}
+
+/// What `--date` says: which date of a commit to use, or how to show it.
+#[derive(Clone, Copy)]
+enum DateOption {
+    Kind(DateKind),
+    Relative,
+}
+
+impl FromStr for DateOption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "author" => Ok(DateOption::Kind(DateKind::Author)),
+            "committer" => Ok(DateOption::Kind(DateKind::Committer)),
+            "relative" => Ok(DateOption::Relative),
+            _ => Err(format!(
+                "unknown date: {}, expected author, committer or relative",
+                s
+            )),
+        }
+    }
+}
+
+/// Which timezone `--date-zone` shows dates in.
+#[derive(Clone, Copy, Default)]
+enum DateZone {
+    #[default]
+    Original,
+    Local,
+    Utc,
+}
+
+impl FromStr for DateZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "original" => Ok(DateZone::Original),
+            "local" => Ok(DateZone::Local),
+            "utc" => Ok(DateZone::Utc),
+            _ => Err(format!(
+                "unknown timezone: {}, expected original, local or utc",
+                s
+            )),
+        }
+    }
+}
+
+/// How `--group-by` organizes the log.
+#[derive(Clone, Copy)]
+enum GroupBy {
+    Team,
+    Author,
+    Week,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "team" => Ok(GroupBy::Team),
+            "author" => Ok(GroupBy::Author),
+            "week" => Ok(GroupBy::Week),
+            _ => Err(format!(
+                "unknown grouping: {}, expected team, author or week",
+                s
+            )),
+        }
+    }
+}
+
+/// What `--format` prints the log as.
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Text,
+    Json,
+    Csv,
+    Tsv,
+    Markdown,
+    Html,
+    Ics,
+    Parquet,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "markdown" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            "ics" => Ok(Format::Ics),
+            "parquet" => Ok(Format::Parquet),
+            _ => Err(format!(
+                "unknown format: {}, expected text, json, csv, tsv, markdown, html, ics or parquet",
+                s
+            )),
+        }
+    }
+}
+
+impl Format {
+    // The format a file name suggests, e.g. log.md
+    fn from_extension(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "tsv" => Some(Format::Tsv),
+            "md" | "markdown" => Some(Format::Markdown),
+            "html" | "htm" => Some(Format::Html),
+            "ics" => Some(Format::Ics),
+            "parquet" => Some(Format::Parquet),
+            _ => None,
+        }
+    }
+}
+
+/// A column of `--format csv` and `--format tsv`.
+#[derive(Clone, Copy)]
+enum Column {
+    Sha,
+    Repo,
+    Author,
+    Email,
+    Team,
+    Date,
+    Subject,
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha" => Ok(Column::Sha),
+            "repo" => Ok(Column::Repo),
+            "author" => Ok(Column::Author),
+            "email" => Ok(Column::Email),
+            "team" => Ok(Column::Team),
+            "date" => Ok(Column::Date),
+            "subject" => Ok(Column::Subject),
+            _ => Err(format!(
+                "unknown column: {}, expected sha, repo, author, email, team, date or subject",
+                s
+            )),
+        }
+    }
+}
+
+impl Column {
+    fn name(self) -> &'static str {
+        match self {
+            Column::Sha => "sha",
+            Column::Repo => "repo",
+            Column::Author => "author",
+            Column::Email => "email",
+            Column::Team => "team",
+            Column::Date => "date",
+            Column::Subject => "subject",
+        }
+    }
+
+    fn value(self, commit: &GlobalCommit) -> String {
+        match self {
+            Column::Sha => commit.sha.clone(),
+            Column::Repo => commit.repo_name.clone(),
+            Column::Author => commit.author.clone(),
+            Column::Email => commit.author_email.clone(),
+            Column::Team => commit.team.clone().unwrap_or_default(),
+            Column::Date => dates()
+                .in_zone(&commit.date)
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            Column::Subject => commit
+                .message
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+}
+
+#[derive(StructOpt)]
+struct LogArgs {
+    #[structopt(name = "since", long, short)]
+    /// How far into the past should we go?  e.g. 2022-12-31 or "2 weeks ago"; defaults to one week ago
+    since: Option<String>,
+
     #[structopt(name = "until", long, short)]
-    /// How far into the past should we go?  e.g. 2022-12-31; defaults to one week ago
+    /// Ignore commits newer than this date, e.g. 2023-01-31 or yesterday; defaults to now
     until: Option<String>,
 
+    #[structopt(name = "date", long, number_of_values = 1)]
+    /// Which date of a commit to show and sort by: author (the default) or committer; or relative, to show dates as
+    /// e.g. "3 hours ago".  Can be given twice, e.g. --date committer --date relative
+    date: Vec<DateOption>,
+
+    #[structopt(name = "date-zone", long, default_value = "original")]
+    /// Which timezone to show dates in: original, the one each commit was made in, local or utc
+    date_zone: DateZone,
+
+    #[structopt(name = "utc", long)]
+    /// Use UTC instead of the local timezone, for --since, --until and --date-zone local; ggl falls back to it anyway
+    /// when it can't tell the local timezone
+    utc: bool,
+
+    #[structopt(name = "date-format", long)]
+    /// How to show dates: git, iso8601, short, or a format like "[year]-[month]-[day] [hour]:[minute]"; overrides the
+    /// date_format of the config
+    date_format: Option<String>,
+
     #[structopt(name = "fetch", long, short)]
     /// Run git fetch
     fetch: bool,
 
     #[structopt(name = "json", long, short)]
-    /// Print JSON
+    /// Print JSON; the same as --format json
     json: bool,
 
+    #[structopt(name = "format", long)]
+    /// What to print the log as: text, json, csv, tsv, markdown, html, a page to save and open in a browser, or ics,
+    /// an iCalendar file with an event for each set of commits, or parquet, for data warehouses; defaults to text
+    format: Option<Format>,
+
+    #[structopt(name = "strict", long)]
+    /// Stop at the first repository that can't be fetched or walked, rather than showing the log of the others
+    strict: bool,
+
+    #[structopt(name = "fail-if-empty", long)]
+    /// Exit with status 3 when there are no commits to show
+    fail_if_empty: bool,
+
+    #[structopt(name = "output", long, short, parse(from_os_str))]
+    /// Write the log to this file instead, in the format its extension suggests, e.g. log.json, log.md, log.csv or
+    /// log.html, unless --format says otherwise
+    output: Option<PathBuf>,
+
+    #[structopt(
+        name = "columns",
+        long,
+        use_delimiter = true,
+        default_value = "sha,repo,author,date,subject"
+    )]
+    /// The columns of --format csv and tsv: sha, repo, author, email, team, date or subject
+    columns: Vec<Column>,
+
+    #[structopt(name = "subject-only", long)]
+    /// Only show the first line of each commit's message; the default with --oneline, --merges and --group-by
+    subject_only: bool,
+
+    #[structopt(name = "full-body", long, conflicts_with = "subject-only")]
+    /// Show the whole message of each commit, even with --oneline, --merges and --group-by
+    full_body: bool,
+
+    #[structopt(name = "width", long)]
+    /// Wrap messages and cut one-line entries to this many columns, or 0 for no limit; defaults to the width of the
+    /// terminal, with no limit when not printing to one
+    width: Option<usize>,
+
+    #[structopt(name = "abbrev", long)]
+    /// Show commits by the shortest SHA that's still unique in their repository, of at least this many digits; JSON
+    /// always has the full SHA
+    abbrev: Option<Option<usize>>,
+
+    #[structopt(name = "oneline", long)]
+    /// Print each commit on one line: its short SHA, date, repository and subject
+    oneline: bool,
+
+    #[structopt(name = "pretty", long)]
+    /// Print each commit on a line of its own, laid out like git log --pretty=format:, e.g. "%h %R %an %s"; also takes
+    /// %H, %ae, %ad, %as, %ai, %aI, %at, %ar, %cn, %ce, %cd, %cr, %b, %B, %d, %n and %%
+    pretty: Option<String>,
+
+    #[structopt(name = "template", long, parse(from_os_str))]
+    /// Render the log with this Tera template instead of --format; see the README for what it gets
+    template: Option<PathBuf>,
+
+    #[structopt(name = "json-sets", long, conflicts_with = "group-by")]
+    /// Print JSON, keeping each merge and the commits it brought in together in a set
+    json_sets: bool,
+
+    #[structopt(name = "group-by", long)]
+    /// Show the commits of each team, author, or ISO week together: team, author or week
+    group_by: Option<GroupBy>,
+
     #[structopt(name = "reverse", long, short)]
     /// Reverse the result
     reverse: bool,
 
-    #[structopt(name = "config", long, short)]
-    /// Path to config file
-    config: Option<PathBuf>,
-}
+    #[structopt(name = "name-only", long, conflicts_with = "name-status")]
+    /// List the files each commit changed
+    name_only: bool,
+
+    #[structopt(name = "name-status", long)]
+    /// List the files each commit changed, along with how they changed
+    name_status: bool,
+
+    #[structopt(name = "stat", long)]
+    /// Show how many lines each commit added and removed in each file
+    stat: bool,
+
+    #[structopt(name = "numstat", long, conflicts_with = "stat")]
+    /// Like --stat, but with plain numbers, for scripts
+    numstat: bool,
+
+    #[structopt(name = "patch", long, short)]
+    /// Show the diff of each commit
+    patch: bool,
+
+    #[structopt(name = "path", long, number_of_values = 1)]
+    /// Only show commits that change files matching this path or glob; may be given more than once
+    path: Vec<String>,
+
+    #[structopt(name = "ext", long, number_of_values = 1)]
+    /// Only show commits that change files with this extension, e.g. sql; may be given more than once, and with
+    /// --path, only files that match both count
+    ext: Vec<String>,
+
+    #[structopt(name = "grep", long, number_of_values = 1)]
+    /// Only show commits whose message matches this regular expression; may be given more than once
+    grep: Vec<String>,
+
+    #[structopt(name = "invert-grep", long)]
+    /// Only show commits whose message doesn't match --grep
+    invert_grep: bool,
+
+    #[structopt(name = "author", long, number_of_values = 1)]
+    /// Only show commits whose author, as "Name <email>", matches this regular expression; may be given more than once
+    author: Vec<String>,
+
+    #[structopt(name = "exclude-author", long, number_of_values = 1)]
+    /// Hide commits whose author matches this regular expression, e.g. a bot; may be given more than once
+    exclude_author: Vec<String>,
+
+    #[structopt(name = "type", long, use_delimiter = true)]
+    /// Only show commits with these Conventional Commits types, e.g. feat,fix
+    types: Vec<String>,
+
+    #[structopt(name = "trailer", long, number_of_values = 1)]
+    /// Only show commits with this trailer, e.g. Reviewed-by, or with a value matching a regular expression, e.g.
+    /// Reviewed-by=alice; may be given more than once
+    trailer: Vec<String>,
+
+    #[structopt(name = "trailers", long)]
+    /// Show the trailers of each commit, e.g. Signed-off-by, under its date
+    trailers: bool,
+
+    #[structopt(name = "hide-reverted", long)]
+    /// Hide reverts along with the commits they revert
+    hide_reverted: bool,
+
+    #[structopt(name = "dedup", long)]
+    /// Show commits that made the same change, e.g. cherry-picks into other repositories, only once
+    dedup: bool,
+
+    #[structopt(name = "change-id", long)]
+    /// Show commits with the same Gerrit Change-Id, e.g. backports to several branches, only once
+    change_id: bool,
+
+    #[structopt(name = "show-signatures", long)]
+    /// Check the GPG or SSH signature of each commit, and show whether it's good, bad, unknown or missing
+    show_signatures: bool,
+
+    #[structopt(name = "notes", long)]
+    /// Show the notes in this notes ref, e.g. ci for refs/notes/ci, under each commit, instead of the ones configured
+    /// for each repository
+    notes: Option<String>,
+
+    #[structopt(name = "released", long)]
+    /// Show the first release, i.e. the oldest tag, that contains each commit
+    released: bool,
 
-#[derive(Debug, Deserialize)]
-enum GglError {
-    ConfigParserError(String),
-    GitError(String),
-    MissingConfigFile,
+    #[structopt(name = "first-parent", long)]
+    /// Only follow the first parent of merges, showing each merge as a single commit
+    first_parent: bool,
+
+    #[structopt(name = "merges", long, conflicts_with = "no-merges")]
+    /// Only show merge commits, one line each
+    merges: bool,
+
+    #[structopt(name = "no-merges", long)]
+    /// Leave out merge commits, but still show the commits each merge brought in together
+    no_merges: bool,
+
+    #[structopt(name = "jobs", long)]
+    /// Number of repositories to process concurrently; defaults to the number of CPUs
+    jobs: Option<usize>,
+
+    #[structopt(name = "fetch-jobs", long)]
+    /// Number of repositories to fetch concurrently with --fetch; defaults to --jobs
+    fetch_jobs: Option<usize>,
 }
 
-impl From<git2::Error> for GglError {
-    fn from(err: git2::Error) -> Self {
-        GglError::GitError(err.message().to_owned())
+impl LogArgs {
+    fn date_kind(&self) -> DateKind {
+        let mut kinds = self.date.iter().filter_map(|date| match date {
+            DateOption::Kind(kind) => Some(*kind),
+            DateOption::Relative => None,
+        });
+        kinds.next_back().unwrap_or_default()
+    }
+
+    fn relative_dates(&self) -> bool {
+        self.date
+            .iter()
+            .any(|date| matches!(date, DateOption::Relative))
     }
-}
 
-impl From<serde_yaml::Error> for GglError {
-    fn from(err: serde_yaml::Error) -> Self {
-        GglError::ConfigParserError(format!("{}", err))
+    // Whether each commit gets a single line
+    fn compact(&self) -> bool {
+        self.merges || self.oneline || self.pretty.is_some()
+    }
+
+    // Whether to show the whole message, or just the subject.  The one-line
+    // formats and the groups are for skimming.
+    fn full_body(&self) -> bool {
+        match (self.full_body, self.subject_only) {
+            (true, _) => true,
+            (_, true) => false,
+            _ => !self.compact() && self.group_by.is_none(),
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
-enum FilterType {
-    Include,
-    Reject,
+/// How the log shows dates.
+struct Dates {
+    relative: bool,
+    zone: DateZone,
+    format: OwnedFormatItem,
+    now: time::OffsetDateTime,
 }
 
-#[derive(Debug, Deserialize)]
-struct Filter {
-    filter_type: FilterType,
-    paths: Vec<String>,
+impl Default for Dates {
+    fn default() -> Dates {
+        Dates {
+            relative: false,
+            zone: DateZone::Original,
+            format: ggl::date_format("git").unwrap(),
+            now: time::OffsetDateTime::now_utc(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct Repository {
-    name: String,
-    path: String,
-    remote: String,
-    branch: String,
-    fetch: bool,
-    filters: Option<Vec<Filter>>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Block {
-    root: String,
-    repositories: Vec<Repository>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Config {
-    blocks: Vec<Block>,
-}
-
-#[derive(Debug, Serialize, Clone)]
-struct GlobalCommit {
-    author: String,
-    date: time::OffsetDateTime,
-    message: String,
-    repo_name: String,
-    sha: String,
-}
-
-/// A CommitSet represents a unit of change to a repo.  It's either:
-///
-/// 1.  A single commit committed to your selected branch
-/// 2.  One or more commits introduced to your branch by a merge commit
-///
-/// The purpose of this tool is to find commits that broke things.
-///
-/// Here is how we create these sets:
-///
-/// For every repository, we walk in topological order.
-///
-/// If we see a commit that isn't a merge, we create a set with a single item.
-/// The date is the date of that commit.
-///
-/// If we see a commit that is a merge, we collect commits until we hit the
-/// SHA of the first parent of that commit.  The date is the date of the
-/// merge commit.
-///
-/// These CommitSets can then be sorted by date, and printed.
-#[derive(Debug)]
-struct CommitSet {
-    date: time::OffsetDateTime,
-    commits: Vec<GlobalCommit>,
-}
-
-type CommitSetResult = Result<Vec<CommitSet>, GglError>;
-
-fn load_config(path: PathBuf) -> Result<Config, GglError> {
-    let contents = fs::read_to_string(path).unwrap();
-    // TODO: Not sure why we can't return:
-    //    serde_yaml::from_str(&contents)?;
-    match serde_yaml::from_str(&contents) {
-        Ok(c) => Ok(c),
-        Err(e) => Err(GglError::ConfigParserError(format!("{}", e))),
-    }
-}
-
-fn git_fetch(repo: &git2::Repository, r: &Repository) -> Result<(), git2::Error> {
-    if !r.fetch {
-        return Ok(());
+impl Dates {
+    // The same moment as `date`, in the timezone we show dates in
+    fn in_zone(&self, date: &time::OffsetDateTime) -> time::OffsetDateTime {
+        match self.zone {
+            DateZone::Original => *date,
+            DateZone::Utc => date.to_offset(time::UtcOffset::UTC),
+            DateZone::Local => date.to_offset(ggl::local_offset_at(*date)),
+        }
     }
 
-    println!("Fetching {} {}/{}", &r.name, &r.remote, &r.branch);
-    repo.find_remote(&r.remote)?.fetch(&[&r.branch], None, None)
+    // A date in the format we were given, or how long ago it was
+    fn show(&self, date: &time::OffsetDateTime) -> String {
+        if self.relative {
+            return relative_date(date, &self.now);
+        }
+        self.in_zone(date).format(&self.format).unwrap_or_default()
+    }
+
+    // Just the day, for the one-line formats
+    fn show_day(&self, date: &time::OffsetDateTime) -> String {
+        if self.relative {
+            return relative_date(date, &self.now);
+        }
+        let f = time::format_description::parse("[year]-[month]-[day]").unwrap();
+        self.in_zone(date).format(&f).unwrap()
+    }
+}
+
+static DATES: OnceLock<Dates> = OnceLock::new();
+
+fn dates() -> &'static Dates {
+    DATES.get_or_init(Dates::default)
 }
 
-fn should_be_included(filters: &Vec<Filter>, changed_files: &Vec<PathBuf>) -> bool {
-    if filters.len() == 0 {
-        return true;
+// How long before `now` `date` was, rounded the way git rounds it, e.g.
+// "3 hours ago" or "2 years, 5 months ago"
+fn relative_date(date: &time::OffsetDateTime, now: &time::OffsetDateTime) -> String {
+    let seconds = (*now - *date).whole_seconds();
+    if seconds < 0 {
+        return "in the future".to_string();
     }
-    for filter in filters {
-        for filter_path in &filter.paths {
-            for file in changed_files {
-                if file.to_str().unwrap().contains(filter_path) {
-                    match filter.filter_type {
-                        FilterType::Include => {
-                            return true;
-                        }
-                        FilterType::Reject => {
-                            return false;
-                        }
-                    }
-                }
-            }
+    let count = |n: i64, unit: &str| format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+    let ago = |n: i64, unit: &str| format!("{} ago", count(n, unit));
+    let minutes = (seconds + 30) / 60;
+    let hours = (minutes + 30) / 60;
+    let days = (hours + 12) / 24;
+    if seconds < 90 {
+        ago(seconds, "second")
+    } else if minutes < 90 {
+        ago(minutes, "minute")
+    } else if hours < 36 {
+        ago(hours, "hour")
+    } else if days < 14 {
+        ago(days, "day")
+    } else if days < 70 {
+        ago((days + 3) / 7, "week")
+    } else if days < 365 {
+        ago((days + 15) / 30, "month")
+    } else if days < 1825 {
+        let months = (days * 12 * 2 + 365) / (365 * 2);
+        match months % 12 {
+            0 => ago(months / 12, "year"),
+            rest => format!("{}, {}", count(months / 12, "year"), ago(rest, "month")),
         }
+    } else {
+        ago((days + 183) / 365, "year")
+    }
+}
 
-        // If we didn't find a match above
-        match filter.filter_type {
-            FilterType::Include => {
-                return false;
-            }
-            FilterType::Reject => {
-                return true;
-            }
+/// The colors of the log, from the config's theme and its repositories.
+#[derive(Default)]
+struct Colors {
+    sha: Option<Color>,
+    repo: Option<Color>,
+    date: Option<Color>,
+    header: Option<Color>,
+    repos: HashMap<String, Color>,
+}
+
+impl Colors {
+    // Colors that don't parse are left out; validate tells about them
+    fn new(config: &Config) -> Colors {
+        let theme = &config.theme;
+        let parse = |color: &Option<String>| color.as_ref()?.parse().ok();
+        Colors {
+            sha: parse(&theme.sha),
+            repo: parse(&theme.repo),
+            date: parse(&theme.date),
+            header: parse(&theme.header),
+            repos: config
+                .blocks
+                .iter()
+                .flat_map(|block| &block.repositories)
+                .filter_map(|r| Some((r.name.clone(), parse(&r.color)?)))
+                .collect(),
         }
     }
+}
+
+static COLORS: OnceLock<Colors> = OnceLock::new();
 
-    // This should never happen :)
-    true
+fn colors() -> &'static Colors {
+    COLORS.get_or_init(Colors::default)
 }
 
-fn collect_commitsets(config: &Config, fetch: bool, until: git2::Time) -> CommitSetResult {
-    let mut commitsets: Vec<CommitSet> = vec![];
-    for block in &config.blocks {
-        for r in &block.repositories {
-            let repo_path = Path::new(&block.root).join(&r.path);
-            let repo = git2::Repository::open(repo_path)?;
+fn paint(text: &str, color: Option<Color>) -> ColoredString {
+    match color {
+        Some(color) => text.color(color),
+        None => text.normal(),
+    }
+}
 
-            if fetch {
-                git_fetch(&repo, r)?;
-            }
+fn sha(text: &str) -> ColoredString {
+    text.color(colors().sha.unwrap_or(Color::Yellow))
+}
 
-            let sets = collect_commitsets_for_repo(repo, &r, until)?;
-            commitsets.extend(sets);
+// Whether the terminal turns OSC 8 escapes into links.  There's no way to ask
+// it, so we go by the terminals known to; FORCE_HYPERLINK=1 or 0 says
+// otherwise.
+fn supports_hyperlinks(terminal: bool) -> bool {
+    if let Ok(force) = env::var("FORCE_HYPERLINK") {
+        return force != "0";
+    }
+    if !terminal {
+        return false;
+    }
+    let is = |name: &str, values: &[&str]| {
+        env::var(name).is_ok_and(|value| values.contains(&value.as_str()))
+    };
+    let set = |name: &str| env::var_os(name).is_some();
+    // GNOME Terminal and the like, since 0.50
+    let vte = env::var("VTE_VERSION")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok());
+
+    is(
+        "TERM_PROGRAM",
+        &["iTerm.app", "WezTerm", "vscode", "ghostty", "Hyper"],
+    ) || is(
+        "TERM",
+        &["xterm-kitty", "alacritty", "foot", "xterm-ghostty"],
+    ) || vte.is_some_and(|version| version >= 5000)
+        || set("WT_SESSION")
+        || set("KONSOLE_VERSION")
+        || set("DOMTERM")
+}
+
+static HYPERLINKS: OnceLock<bool> = OnceLock::new();
+
+// `text`, linked to `url` if the terminal can show links
+fn link(text: impl std::fmt::Display, url: Option<&str>) -> String {
+    match url {
+        Some(url)
+            if *HYPERLINKS.get_or_init(|| supports_hyperlinks(io::stdout().is_terminal())) =>
+        {
+            format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
         }
+        _ => text.to_string(),
     }
-    commitsets.sort_by_key(|set| set.date);
-    commitsets.reverse();
-    Ok(commitsets)
 }
 
-fn collect_commitsets_for_repo(
-    repo: git2::Repository,
-    r: &Repository,
-    until: git2::Time,
-) -> CommitSetResult {
-    let mut commitsets: Vec<CommitSet> = vec![];
-    let mut revwalk = repo.revwalk()?;
-    let git_ref = format!("refs/remotes/{}/{}", r.remote, r.branch);
-    revwalk.push_ref(&git_ref)?;
-    revwalk.set_sorting(git2::Sort::TOPOLOGICAL)?;
-    let mut diffopts = git2::DiffOptions::new();
+// Those of the other repositories are picked from these, which read well on
+// dark and light backgrounds alike
+static PALETTE: [Color; 8] = [
+    Color::Red,
+    Color::Green,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::BrightRed,
+    Color::BrightGreen,
+    Color::BrightMagenta,
+];
+
+// FNV-1a, so that a name gets the same color every time
+fn name_hash(name: &str) -> u32 {
+    name.bytes().fold(2166136261u32, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(16777619)
+    })
+}
 
-    let mut commit_buffer: Vec<GlobalCommit> = vec![];
-    let mut collecting_commits = false;
-    let mut set_date: time::OffsetDateTime = time::OffsetDateTime::now_utc();
-    let mut destination_commit_id: git2::Oid = git2::Oid::zero();
+fn repo_name(name: &str) -> ColoredString {
+    let colors = colors();
+    let color = colors
+        .repos
+        .get(name)
+        .copied()
+        .or(colors.repo)
+        .unwrap_or(PALETTE[name_hash(name) as usize % PALETTE.len()]);
+    name.color(color)
+}
 
-    for id in revwalk {
-        let id = id?;
-        let commit = repo.find_commit(id)?;
-        let commit_date = commit.author().when();
+fn print_commit_set(out: &mut dyn Write, set: &mut CommitSet, args: &LogArgs) -> io::Result<()> {
+    if args.reverse {
+        set.commits.reverse();
+    }
 
-        let is_merge = commit.parent_count() > 1;
+    if !args.compact() {
+        print_set_header(out, set)?;
+    }
+    let has_merge = set.commits.iter().any(|commit| commit.merge);
+    for commit in &set.commits {
+        // On one line each, the commits a merge brought in are indented
+        let indent = match args.oneline && has_merge && !commit.merge {
+            true => "  ",
+            false => "",
+        };
+        print_commit(out, commit, args, indent)?;
+    }
+    Ok(())
+}
 
-        // Any merge older than `until' should immediately terminate the loop.`
-        if is_merge && commit_date < until {
-            let set = CommitSet {
-                date: set_date,
-                commits: commit_buffer.clone(),
-            };
+// A line to tell sets apart, and for those of a merge, what was merged.
+fn print_set_header(out: &mut dyn Write, set: &CommitSet) -> io::Result<()> {
+    let first = match set.commits.first() {
+        Some(first) => first,
+        None => return Ok(()),
+    };
+    writeln!(out, "{}", "-".repeat(72).dimmed())?;
+    let merge = set.commits.iter().find(|commit| commit.merge);
+    if merge.is_none() && set.commits.len() == 1 {
+        return Ok(());
+    }
 
-            commitsets.push(set);
-            break;
+    let subject = match merge {
+        Some(merge) => merge.message.lines().next().unwrap_or_default(),
+        None => "Merged",
+    };
+    let header = format!(
+        "{} ({}, {}, {})",
+        subject,
+        first.repo_name,
+        commit_count(set.commits.len()),
+        dates().show(&set.date)
+    );
+    writeln!(out, "{}\n", paint(&header, colors().header).bold())?;
+    Ok(())
+}
+
+fn print_commit(
+    out: &mut dyn Write,
+    commit: &GlobalCommit,
+    args: &LogArgs,
+    indent: &str,
+) -> io::Result<()> {
+    if let Some(format) = &args.pretty {
+        writeln!(out, "{}", pretty(format, commit))?;
+    } else if args.merges || args.oneline {
+        print_oneline(out, commit, indent)?;
+        if args.full_body() {
+            let body = commit.message.lines().skip(1).skip_while(|l| l.is_empty());
+            for line in body {
+                print_indented(out, line)?;
+            }
         }
+    } else {
+        print_global_commit(out, commit, args)?;
+    }
+    Ok(())
+}
 
-        // In trunk-based repos (i.e. no PR merges, or flat history), we need to check non-merges, too.
-        if !is_merge && !collecting_commits {
-            if commit_date < until {
-                break;
+// Fill in the placeholders of a git pretty format.  Like git, we leave the
+// ones we don't know alone.
+fn pretty(format: &str, commit: &GlobalCommit) -> String {
+    let format = format
+        .strip_prefix("format:")
+        .or_else(|| format.strip_prefix("tformat:"))
+        .unwrap_or(format);
+    let short_date = time::format_description::parse("[year]-[month]-[day]").unwrap();
+    let iso_date = time::format_description::parse(
+        "[year]-[month]-[day] [hour]:[minute]:[second] \
+         [offset_hour sign:mandatory][offset_minute]",
+    )
+    .unwrap();
+    let strict_iso_date = time::format_description::parse(
+        "[year]-[month]-[day]T[hour]:[minute]:[second]\
+         [offset_hour sign:mandatory]:[offset_minute]",
+    )
+    .unwrap();
+    let date = |date: &time::OffsetDateTime, style: char| -> Option<String> {
+        let shown = dates().in_zone(date);
+        let date = &shown;
+        match style {
+            'd' => Some(dates().show(date)),
+            's' => date.format(&short_date).ok(),
+            'i' => date.format(&iso_date).ok(),
+            'I' => date.format(&strict_iso_date).ok(),
+            't' => Some(date.unix_timestamp().to_string()),
+            'r' => Some(relative_date(date, &dates().now)),
+            _ => None,
+        }
+    };
+    let (committer, committer_email, commit_date) = match &commit.committer {
+        Some(committer) => (&committer.name, &committer.email, &committer.date),
+        None => (&commit.author, &commit.author_email, &commit.date),
+    };
+    let (subject, body) = match commit.message.split_once('\n') {
+        Some((subject, body)) => (subject, body.trim_start_matches('\n')),
+        None => (commit.message.as_str(), ""),
+    };
+
+    let mut line = String::new();
+    let mut rest = format;
+    while let Some(at) = rest.find('%') {
+        line.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+        let mut chars = rest.chars();
+        let (value, length) = match (chars.next(), chars.next()) {
+            (Some('H'), _) => (Some(commit.sha.clone()), 1),
+            (Some('h'), _) => (Some(commit.short_sha.clone()), 1),
+            (Some('R'), _) => (Some(commit.repo_name.clone()), 1),
+            (Some('s'), _) => (Some(subject.to_string()), 1),
+            (Some('b'), _) => (Some(body.to_string()), 1),
+            (Some('B'), _) => (Some(commit.message.clone()), 1),
+            (Some('d'), _) if commit.tags.is_empty() => (Some(String::new()), 1),
+            (Some('d'), _) => {
+                let tags: Vec<String> = commit
+                    .tags
+                    .iter()
+                    .map(|tag| format!("tag: {}", tag))
+                    .collect();
+                (Some(format!(" ({})", tags.join(", "))), 1)
+            }
+            (Some('n'), _) => (Some("\n".to_string()), 1),
+            (Some('%'), _) => (Some("%".to_string()), 1),
+            (Some('a'), Some('n')) => (Some(commit.author.clone()), 2),
+            (Some('a'), Some('e')) => (Some(commit.author_email.clone()), 2),
+            (Some('a'), Some(style)) => (date(&commit.date, style), 2),
+            (Some('c'), Some('n')) => (Some(committer.clone()), 2),
+            (Some('c'), Some('e')) => (Some(committer_email.clone()), 2),
+            (Some('c'), Some(style)) => (date(commit_date, style), 2),
+            _ => (None, 0),
+        };
+        match value {
+            Some(value) => {
+                line.push_str(&value);
+                rest = &rest[length..];
+            }
+            None => line.push('%'),
+        }
+    }
+    line.push_str(rest);
+    line
+}
+
+fn print_oneline(out: &mut dyn Write, commit: &GlobalCommit, indent: &str) -> io::Result<()> {
+    let date = dates().show_day(&commit.date);
+    let subject = commit.message.lines().next().unwrap_or_default();
+    let subject = match width() {
+        Some(width) => {
+            let taken = display_width(&format!(
+                "{}{} {} {} ",
+                indent, commit.short_sha, date, commit.repo_name
+            ));
+            truncate(subject, width.saturating_sub(taken))
+        }
+        None => subject.into(),
+    };
+    writeln!(
+        out,
+        "{}{} {} {} {}",
+        indent,
+        link(sha(&commit.short_sha), commit.url.as_deref()),
+        paint(&date, colors().date),
+        repo_name(&commit.repo_name),
+        subject
+    )?;
+    Ok(())
+}
+
+fn print_global_commit(
+    out: &mut dyn Write,
+    commit: &GlobalCommit,
+    args: &LogArgs,
+) -> io::Result<()> {
+    let commit_sha = match args.abbrev {
+        Some(_) => &commit.short_sha,
+        None => &commit.sha,
+    };
+    let commit_line = format!(
+        "{} {}",
+        sha("commit"),
+        link(sha(commit_sha), commit.url.as_deref())
+    );
+    if commit.tags.is_empty() {
+        writeln!(out, "{}", commit_line)?;
+    } else {
+        let tags: Vec<String> = commit
+            .tags
+            .iter()
+            .map(|tag| format!("tag: {}", tag))
+            .collect();
+        writeln!(
+            out,
+            "{} {}{}{}",
+            commit_line,
+            sha("("),
+            sha(&tags.join(", ")).bold(),
+            sha(")")
+        )?;
+    }
+    writeln!(out, "Repo:   {}", repo_name(&commit.repo_name))?;
+    writeln!(out, "Author: {}", commit.author)?;
+    print_time(out, "Date:   ", &commit.date)?;
+    if let Some(committer) = &commit.committer {
+        writeln!(out, "Commit: {} <{}>", committer.name, committer.email)?;
+        print_time(out, "CommitDate: ", &committer.date)?;
+    }
+    if args.released {
+        let release = commit.released_in.as_deref().unwrap_or("not yet");
+        writeln!(out, "Released in: {}", release)?;
+    }
+    if let Some(reverted) = &commit.reverts {
+        writeln!(out, "Reverts: {}", reverted)?;
+    }
+    if let Some(revert) = &commit.reverted_by {
+        writeln!(out, "Reverted by: {}", revert)?;
+    }
+    match commit.signature {
+        Some(SignatureStatus::Good) => writeln!(out, "Signature: {}", "Good".green())?,
+        Some(SignatureStatus::Bad) => writeln!(out, "Signature: {}", "Bad".red())?,
+        Some(SignatureStatus::Unknown) => writeln!(out, "Signature: Unknown")?,
+        Some(SignatureStatus::Unsigned) => writeln!(out, "Signature: Unsigned")?,
+        None => {}
+    }
+    for copy in &commit.copies {
+        writeln!(out, "Also in: {} {}", copy.repo_name, copy.sha)?;
+    }
+    if args.trailers {
+        for (key, values) in &commit.trailers {
+            for value in values {
+                writeln!(out, "{}: {}", key, value)?;
             }
         }
+    }
+    writeln!(out)?;
 
-        if !is_merge {
-            if let Some(filters) = &r.filters {
-                let mut changed_files: Vec<PathBuf> = vec![];
-                let current_tree = commit.tree()?;
+    let lines = commit.message.lines();
+    for line in lines.take(if args.full_body() { usize::MAX } else { 1 }) {
+        print_indented(out, line)?;
+    }
 
-                let parent_tree = if commit.parent_count() == 1 {
-                    Some(commit.parent(0)?.tree()?)
-                } else {
-                    None
-                };
+    writeln!(out)?;
 
-                let diff = repo.diff_tree_to_tree(
-                    parent_tree.as_ref(),
-                    Some(&current_tree),
-                    Some(&mut diffopts),
-                )?;
+    if let Some(note) = &commit.note {
+        writeln!(out, "Notes:")?;
+        for line in note.lines() {
+            print_indented(out, line)?;
+        }
+        writeln!(out)?;
+    }
 
-                for delta in diff.deltas() {
-                    let new_file = delta.new_file();
-                    changed_files.push(new_file.path().unwrap().to_owned());
-                }
+    print_files(out, &commit.files, args)?;
+    if let Some(patch) = &commit.patch {
+        print_patch(out, patch)?;
+    }
+    Ok(())
+}
 
-                if !should_be_included(filters, &changed_files) {
-                    continue;
+fn print_files(out: &mut dyn Write, files: &[ChangedFile], args: &LogArgs) -> io::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    if args.stat {
+        print_stat(out, files)?;
+        return Ok(());
+    }
+    for file in files {
+        if args.numstat {
+            match (file.insertions, file.deletions) {
+                (Some(insertions), Some(deletions)) => {
+                    write!(out, "{}\t{}\t", insertions, deletions)?
                 }
+                _ => write!(out, "-\t-\t")?,
+            }
+            writeln!(out, "{}", stat_name(file))?;
+            continue;
+        }
+        match (&file.old_path, args.name_status) {
+            (_, false) => writeln!(out, "{}", file.path)?,
+            (Some(old_path), true) => {
+                writeln!(out, "{}\t{}\t{}", file.status, old_path, file.path)?
             }
+            (None, true) => writeln!(out, "{}\t{}", file.status, file.path)?,
+        }
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+fn print_patch(out: &mut dyn Write, patch: &str) -> io::Result<()> {
+    for line in patch.lines() {
+        if line.starts_with("diff ")
+            || line.starts_with("index ")
+            || line.starts_with("--- ")
+            || line.starts_with("+++ ")
+        {
+            writeln!(out, "{}", line.bold())?;
+        } else if line.starts_with("@@") {
+            writeln!(out, "{}", line.cyan())?;
+        } else if line.starts_with('+') {
+            writeln!(out, "{}", line.green())?;
+        } else if line.starts_with('-') {
+            writeln!(out, "{}", line.red())?;
+        } else {
+            writeln!(out, "{}", line)?;
         }
+    }
+    writeln!(out)?;
+    Ok(())
+}
 
-        if collecting_commits && commit.id() == destination_commit_id {
-            let set = CommitSet {
-                date: set_date,
-                commits: commit_buffer.clone(),
-            };
+/// How a file is named in --stat and --numstat; renames show both names.
+fn stat_name(file: &ChangedFile) -> String {
+    match &file.old_path {
+        Some(old_path) => format!("{} => {}", old_path, file.path),
+        None => file.path.clone(),
+    }
+}
 
-            // reset
-            commit_buffer.clear();
-            collecting_commits = false;
-            commitsets.push(set);
+fn print_stat(out: &mut dyn Write, files: &[ChangedFile]) -> io::Result<()> {
+    // Like git, scale the graph down so that the widest one fits
+    const GRAPH_WIDTH: usize = 50;
+
+    let names: Vec<String> = files.iter().map(stat_name).collect();
+    let name_width = names
+        .iter()
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let changes = |file: &ChangedFile| file.insertions.unwrap_or(0) + file.deletions.unwrap_or(0);
+    let max_changes = files.iter().map(changes).max().unwrap_or(0);
+    let count_width = max_changes.to_string().len();
+    let scale = |n: usize| match max_changes > GRAPH_WIDTH {
+        true if n > 0 => (n * GRAPH_WIDTH / max_changes).max(1),
+        _ => n,
+    };
+
+    let (mut insertions, mut deletions) = (0, 0);
+    for (file, name) in files.iter().zip(&names) {
+        if file.binary {
+            writeln!(out, " {:<name_width$} | Bin", name)?;
+            continue;
         }
+        let (added, removed) = (file.insertions.unwrap_or(0), file.deletions.unwrap_or(0));
+        insertions += added;
+        deletions += removed;
+        let mut line = format!(" {:<name_width$} | {:>count_width$}", name, added + removed);
+        if added + removed > 0 {
+            line.push(' ');
+        }
+        writeln!(
+            out,
+            "{}{}{}",
+            line,
+            "+".repeat(scale(added)).green(),
+            "-".repeat(scale(removed)).red(),
+        )?;
+    }
 
-        let commit_date = git_time_to_datetime(&commit.author().when())?;
+    let plural = |n: usize, what: &str| match n {
+        1 => format!("1 {}", what),
+        n => format!("{} {}s", n, what),
+    };
+    let mut summary = format!(" {} changed", plural(files.len(), "file"));
+    if insertions > 0 {
+        summary.push_str(&format!(", {}(+)", plural(insertions, "insertion")));
+    }
+    if deletions > 0 {
+        summary.push_str(&format!(", {}(-)", plural(deletions, "deletion")));
+    }
+    writeln!(out, "{}", summary)?;
+    writeln!(out)?;
+    Ok(())
+}
 
-        let global_commit = GlobalCommit {
-            author: commit.author().name().unwrap().to_string(),
-            date: commit_date.clone(),
-            message: commit.message().unwrap().to_string(),
-            sha: commit.id().to_string(),
-            repo_name: r.name.clone(),
-        };
+// How wide the output may be, if it's limited
+static WIDTH: OnceLock<Option<usize>> = OnceLock::new();
 
-        if is_merge {
-            set_date = commit_date.clone();
-            collecting_commits = true;
-            destination_commit_id = commit.parent(0)?.id();
+fn width() -> Option<usize> {
+    *WIDTH.get_or_init(|| None)
+}
 
-            commit_buffer.push(global_commit);
-        } else {
-            if collecting_commits {
-                commit_buffer.push(global_commit);
-                continue;
+#[cfg(unix)]
+fn terminal_width() -> Option<usize> {
+    // SAFETY: TIOCGWINSZ only writes to the winsize we give it
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+#[cfg(not(unix))]
+fn terminal_width() -> Option<usize> {
+    None
+}
+
+// A line of a message, indented, and wrapped to fit the width
+fn print_indented(out: &mut dyn Write, line: &str) -> io::Result<()> {
+    match width() {
+        Some(width) if display_width(line) + 4 > width => {
+            for part in textwrap::wrap(line, width.saturating_sub(4).max(20)) {
+                writeln!(out, "    {}", part)?;
             }
+        }
+        _ => writeln!(out, "    {}", line)?,
+    }
+    Ok(())
+}
 
-            let set = CommitSet {
-                date: commit_date,
-                commits: vec![global_commit],
-            };
+// Cut `text` down to `width` columns, with an ellipsis at the end to show
+// that it was
+fn truncate(text: &str, width: usize) -> Cow<'_, str> {
+    if display_width(text) <= width {
+        return text.into();
+    }
+    let mut cut = String::new();
+    let mut taken = 0;
+    for c in text.chars() {
+        let c_width = display_width(c.encode_utf8(&mut [0; 4]));
+        if taken + c_width + 1 > width {
+            break;
+        }
+        cut.push(c);
+        taken += c_width;
+    }
+    cut.push('…');
+    cut.into()
+}
 
-            commitsets.push(set);
+fn print_time(out: &mut dyn Write, label: &str, t: &time::OffsetDateTime) -> io::Result<()> {
+    writeln!(out, "{}{}", label, paint(&dates().show(t), colors().date))?;
+    Ok(())
+}
+
+// Every commit of `sets`, in the order we show them in.
+fn flatten(sets: &mut [CommitSet], reverse: bool) -> Vec<&GlobalCommit> {
+    let mut commits: Vec<&GlobalCommit> = vec![];
+
+    for set in sets {
+        if reverse {
+            set.commits.reverse();
+        }
+        for commit in &set.commits {
+            commits.push(commit);
         }
     }
+    commits
+}
 
-    Ok(commitsets)
+/// The commits of one group of `--group-by`.
+#[derive(Serialize)]
+struct Group<'a> {
+    /// None for commits that don't belong to any group, e.g. by authors who
+    /// aren't on a team
+    name: Option<String>,
+    commits: Vec<&'a GlobalCommit>,
 }
 
-fn print_commit_set(set: &mut CommitSet, reverse: bool) {
-    if reverse {
-        set.commits.reverse();
+impl Group<'_> {
+    // The group's name and how many commits it has
+    fn heading(&self) -> String {
+        format!(
+            "{} ({})",
+            self.name.as_deref().unwrap_or("No team"),
+            commit_count(self.commits.len())
+        )
     }
+}
 
-    for commit in &set.commits {
-        print_global_commit(commit);
+fn commit_count(n: usize) -> String {
+    match n {
+        1 => "1 commit".to_string(),
+        n => format!("{} commits", n),
     }
 }
 
-fn print_global_commit(commit: &GlobalCommit) {
-    let commit_line = format!("commit {}", commit.sha);
-    println!("{}", commit_line.yellow());
-    println!("Repo:   {}", commit.repo_name);
-    println!("Author: {}", commit.author);
-    print_time(&commit.date);
-    println!();
+// Split `commits` by `group_by`, keeping their order within each group.  The
+// groups are sorted by name, with the commits that belong to none last; weeks
+// go newest first, like the log, unless `reverse`.
+fn group(commits: Vec<&GlobalCommit>, group_by: GroupBy, reverse: bool) -> Vec<Group<'_>> {
+    let mut groups: BTreeMap<Option<String>, Vec<&GlobalCommit>> = BTreeMap::new();
+    for commit in commits {
+        let name = match group_by {
+            GroupBy::Team => commit.team.clone(),
+            GroupBy::Author => Some(commit.author.clone()),
+            GroupBy::Week => {
+                let (year, week, _) = dates().in_zone(&commit.date).to_iso_week_date();
+                Some(format!("{}-W{:02}", year, week))
+            }
+        };
+        groups.entry(name).or_default().push(commit);
+    }
 
-    for line in commit.message.lines() {
-        println!("    {}", line);
+    let rest = groups.remove(&None);
+    let mut groups: Vec<Group> = groups
+        .into_iter()
+        .chain(rest.map(|commits| (None, commits)))
+        .map(|(name, commits)| Group { name, commits })
+        .collect();
+    if let (GroupBy::Week, false) = (group_by, reverse) {
+        groups.reverse();
     }
+    groups
+}
 
-    println!();
+fn print_groups(out: &mut dyn Write, groups: &[Group], args: &LogArgs) -> io::Result<()> {
+    for group in groups {
+        writeln!(out, "{}\n", paint(&group.heading(), colors().header).bold())?;
+        for commit in &group.commits {
+            print_commit(out, commit, args, "")?;
+        }
+        if args.compact() {
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+// Print one row per commit, with a header, separated by `separator`.  Fields
+// are quoted as CSV wants them; TSV has no quoting, so tabs become spaces.
+fn print_table(
+    out: &mut dyn Write,
+    commits: &[&GlobalCommit],
+    columns: &[Column],
+    separator: char,
+) -> io::Result<()> {
+    let field = |value: &str| -> String {
+        if separator == '\t' {
+            value.replace('\t', " ")
+        } else if value.contains([separator, '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+    let row = |values: Vec<String>| -> String {
+        values
+            .iter()
+            .map(|value| field(value))
+            .collect::<Vec<String>>()
+            .join(&separator.to_string())
+    };
+
+    writeln!(
+        out,
+        "{}",
+        row(columns.iter().map(|c| c.name().to_string()).collect())
+    )?;
+    for commit in commits {
+        writeln!(
+            out,
+            "{}",
+            row(columns.iter().map(|c| c.value(commit)).collect())
+        )?;
+    }
+    Ok(())
 }
 
-fn git_time_to_datetime(time: &git2::Time) -> Result<time::OffsetDateTime, GglError> {
-    let off = time::UtcOffset::from_whole_seconds(time.offset_minutes() * 60).unwrap();
+// Backslash anything Markdown would take for formatting.
+fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\`*_[]<>#|".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
 
-    let ts = time::OffsetDateTime::from_unix_timestamp(
-        time.seconds() + (time.offset_minutes() as i64) * 60,
+// The short SHA, linked to the commit's page if we know it, and the rest of
+// what a line of the log says about the commit.
+fn markdown_commit(commit: &GlobalCommit) -> String {
+    let date = time::format_description::parse("[year]-[month]-[day]").unwrap();
+    let sha = format!("`{}`", &commit.short_sha);
+    let sha = match &commit.url {
+        Some(url) => format!("[{}]({})", sha, url),
+        None => sha,
+    };
+    format!(
+        "{} {} ({}, {}, {})",
+        sha,
+        markdown_escape(commit.message.lines().next().unwrap_or_default()),
+        markdown_escape(&commit.author),
+        markdown_escape(&commit.repo_name),
+        dates().in_zone(&commit.date).format(&date).unwrap()
     )
-    .unwrap()
-    .replace_offset(off);
+}
 
-    Ok(ts)
+// Each merge set as a heading, with a list of the commits it brought in;
+// the commits in between sets of their own go into lists of their own.
+fn print_markdown(out: &mut dyn Write, sets: &mut [CommitSet], args: &LogArgs) -> io::Result<()> {
+    let mut in_list = false;
+    for set in sets {
+        if args.reverse {
+            set.commits.reverse();
+        }
+        let merge = set.commits.iter().find(|commit| commit.merge);
+        if args.merges || (merge.is_none() && set.commits.len() == 1) {
+            for commit in &set.commits {
+                writeln!(out, "- {}", markdown_commit(commit))?;
+            }
+            in_list = true;
+            continue;
+        }
+
+        if in_list {
+            writeln!(out)?;
+        }
+        match merge {
+            Some(merge) => {
+                let subject = merge.message.lines().next().unwrap_or_default();
+                writeln!(out, "### {}\n", markdown_escape(subject))?;
+                writeln!(out, "{}\n", markdown_commit(merge))?;
+            }
+            None => writeln!(out, "### Merged\n")?,
+        }
+        for commit in set.commits.iter().filter(|commit| !commit.merge) {
+            writeln!(out, "- {}", markdown_commit(commit))?;
+        }
+        writeln!(out)?;
+        in_list = false;
+    }
+    Ok(())
 }
 
-fn print_time(t: &time::OffsetDateTime) {
-    // Not sure how to do a global const that reqires a function call
-    let f = time::format_description::parse(DATETIME).unwrap();
-    let s = t.format(&f).unwrap();
-    println!("Date:   {}", s);
+fn print_markdown_groups(out: &mut dyn Write, groups: &[Group]) -> io::Result<()> {
+    for group in groups {
+        writeln!(out, "## {}\n", markdown_escape(&group.heading()))?;
+        for commit in &group.commits {
+            writeln!(out, "- {}", markdown_commit(commit))?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
 }
 
-fn get_until(arg: &Option<String>) -> i64 {
+// Render the template at `path` with the sets, all the commits, and their
+// groups, if any.
+fn render_template(
+    path: &Path,
+    sets: &[CommitSet],
+    group_by: Option<GroupBy>,
+    reverse: bool,
+) -> Result<String, GglError> {
+    let template = fs::read_to_string(path)
+        .map_err(|e| GglError::IoError(format!("{}: {}", path.display(), e)))?;
+    let commits: Vec<&GlobalCommit> = sets.iter().flat_map(|set| &set.commits).collect();
+
+    let mut context = tera::Context::new();
+    context.insert("sets", sets);
+    if let Some(group_by) = group_by {
+        context.insert("groups", &group(commits.clone(), group_by, reverse));
+    }
+    context.insert("commits", &commits);
+
+    tera::Tera::one_off(&template, &context, false).map_err(|e| {
+        // What went wrong is usually at the bottom of the chain
+        let mut message = format!("{}: {}", path.display(), e);
+        let mut source = std::error::Error::source(&e);
+        while let Some(e) = source {
+            message.push_str(&format!(": {}", e));
+            source = e.source();
+        }
+        GglError::IoError(message)
+    })
+}
+
+fn print_json<T: Serialize>(out: &mut dyn Write, value: &T) -> io::Result<()> {
+    match serde_json::to_string(value) {
+        Ok(c) => writeln!(out, "{}", c)?,
+        Err(e) => eprintln!("Errror {:?}", e),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(out: &mut (dyn Write + Send), commits: &[&GlobalCommit]) -> Result<(), GglError> {
+    parquet_format::write(commits, out)
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(
+    _out: &mut (dyn Write + Send),
+    _commits: &[&GlobalCommit],
+) -> Result<(), GglError> {
+    Err(GglError::Unsupported(
+        "this ggl was built without Parquet support; build it with --features parquet".to_string(),
+    ))
+}
+
+fn get_jobs(arg: Option<usize>) -> usize {
     match arg {
-        Some(date) => {
-            let format = time::macros::format_description!("[year]-[month]-[day]");
-            let offset = time::UtcOffset::current_local_offset().unwrap();
-            time::Date::parse(date, &format)
-                .unwrap()
-                .with_hms(0, 0, 0)
-                .unwrap()
-                .assume_offset(offset)
-                .unix_timestamp()
-        }
-        None => time::OffsetDateTime::now_local()
-            .unwrap()
-            .saturating_sub(time::Duration::days(7))
-            .unix_timestamp(),
-    }
-}
-
-// Look for a config file in the following places in the following order:
-//   1.  --config flag
-//   2.  $XDG_CONFIG_HOME/ggl.yaml
-//   3.  config.yaml in the current directory
-fn get_config_path(arg_config: Option<PathBuf>) -> Result<PathBuf, GglError> {
-    if let Some(path) = arg_config {
-        if path.exists() {
-            return Ok(path);
-        } else {
-            return Err(GglError::MissingConfigFile);
+        Some(jobs) => jobs,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    }
+}
+
+// How writing the output went.  Once whoever reads it, e.g. head, has had
+// enough, we just stop; SIGPIPE stays ignored, as libgit2 counts on that when
+// a remote hangs up on it.
+fn finish_output(written: io::Result<()>) -> Result<(), GglError> {
+    match written {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        // What went wrong making the output, rather than writing it
+        Err(e) => Err(match e.downcast::<GglError>() {
+            Ok(e) => e,
+            Err(e) => e.into(),
+        }),
+    }
+}
+
+// The log, in `format`, as the arguments say to lay it out
+fn print_log(
+    out: &mut (dyn Write + Send),
+    commitsets: &mut [CommitSet],
+    format: Format,
+    args: &LogArgs,
+) -> io::Result<()> {
+    match (args.group_by, format) {
+        _ if args.template.is_some() => {
+            if args.reverse {
+                for set in commitsets.iter_mut() {
+                    set.commits.reverse();
+                }
+            }
+            let template = args.template.as_ref().unwrap();
+            let rendered = render_template(template, commitsets, args.group_by, args.reverse)
+                .map_err(io::Error::other)?;
+            write!(out, "{}", rendered)
         }
+        _ if args.json_sets => {
+            if args.reverse {
+                for set in commitsets.iter_mut() {
+                    set.commits.reverse();
+                }
+            }
+            print_json(out, &commitsets)
+        }
+        // A calendar is already laid out by date
+        (_, Format::Ics) => write!(out, "{}", ical::render(commitsets)),
+        (_, Format::Parquet) => {
+            write_parquet(out, &flatten(commitsets, args.reverse)).map_err(io::Error::other)
+        }
+        (group_by, Format::Csv | Format::Tsv) => {
+            let mut commits = flatten(commitsets, args.reverse);
+            // A table has no room for headings, so the groups are just runs
+            // of rows
+            if let Some(group_by) = group_by {
+                commits = group(commits, group_by, args.reverse)
+                    .into_iter()
+                    .flat_map(|group| group.commits)
+                    .collect();
+            }
+            let separator = if format == Format::Csv { ',' } else { '\t' };
+            print_table(out, &commits, &args.columns, separator)
+        }
+        (Some(group_by), format) => {
+            let groups = group(flatten(commitsets, args.reverse), group_by, args.reverse);
+            match format {
+                Format::Json => print_json(out, &groups),
+                Format::Markdown => print_markdown_groups(out, &groups),
+                Format::Html => write!(out, "{}", html::render_groups(&groups)),
+                _ => print_groups(out, &groups, args),
+            }
+        }
+        (None, Format::Json) => print_json(out, &flatten(commitsets, args.reverse)),
+        (None, Format::Markdown) => print_markdown(out, commitsets, args),
+        (None, Format::Html) => {
+            if args.reverse {
+                for set in commitsets.iter_mut() {
+                    set.commits.reverse();
+                }
+            }
+            write!(out, "{}", html::render_sets(commitsets))
+        }
+        (None, Format::Text) => {
+            for set in commitsets.iter_mut() {
+                print_commit_set(out, set, args)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn run_log(config: &Config, args: &LogArgs, dry_run: bool) -> Result<(), GglError> {
+    ggl::set_utc(args.utc);
+    let range = get_date_range(&args.since, &args.until)?;
+    let date_format = match args.date_format.as_ref().or(config.date_format.as_ref()) {
+        Some(format) => ggl::date_format(format)?,
+        None => Dates::default().format,
+    };
+    let _ = DATES.set(Dates {
+        relative: args.relative_dates(),
+        zone: args.date_zone,
+        format: date_format,
+        ..Dates::default()
+    });
+    if dry_run {
+        let since = time::OffsetDateTime::from_unix_timestamp(range.since.seconds())
+            .map_err(|e| GglError::InvalidDate(e.to_string()))?;
+        let until = match range.until {
+            Some(until) => dates().show(
+                &time::OffsetDateTime::from_unix_timestamp(until.seconds())
+                    .map_err(|e| GglError::InvalidDate(e.to_string()))?,
+            ),
+            None => "now".to_string(),
+        };
+        let mut out = io::stdout();
+        return finish_output(
+            writeln!(
+                out,
+                "Would show commits from {} until {}\n",
+                dates().show(&since),
+                until
+            )
+            .and_then(|()| print_plan(&mut out, config, args.fetch)),
+        );
     }
 
-    if let Some(path) = dirs::config_dir() {
-        let full_path = path.join("ggl.yaml").to_path_buf();
-        if full_path.exists() {
-            return Ok(full_path);
+    let jobs = get_jobs(args.jobs);
+    let _ = COLORS.set(Colors::new(config));
+    let _ = WIDTH.set(match args.width {
+        Some(0) => None,
+        Some(width) => Some(width),
+        None if args.output.is_none() && io::stdout().is_terminal() => terminal_width(),
+        None => None,
+    });
+    if args.output.is_some() {
+        let _ = HYPERLINKS.set(supports_hyperlinks(false));
+    }
+
+    // Fetch everything up front, so that a slow remote only holds up its own
+    // fetch, and so that one failing remote doesn't stop us from showing the
+    // log of the others.
+    let mut failures: Vec<FetchReport> = if args.fetch {
+        fetch_all(config, get_jobs(args.fetch_jobs.or(args.jobs)))
+            .into_iter()
+            .filter(|report| report.outcome.is_err())
+            .collect()
+    } else {
+        vec![]
+    };
+    if args.strict && !failures.is_empty() {
+        failures.swap_remove(0).outcome?;
+    }
+
+    let format = match (args.json, args.format, &args.output) {
+        (true, _, _) => Format::Json,
+        (_, Some(format), _) => format,
+        (_, None, Some(output)) => Format::from_extension(output).unwrap_or(Format::Text),
+        (_, None, None) => Format::Text,
+    };
+    if cfg!(feature = "parquet")
+        && format == Format::Parquet
+        && args.output.is_none()
+        && io::stdout().is_terminal()
+    {
+        return Err(GglError::Unsupported(
+            "Parquet is a binary format; redirect the output to a file".to_string(),
+        ));
+    }
+    let options = CollectOptions {
+        // The files are one of the columns
+        files: args.name_only || args.name_status || format == Format::Parquet,
+        stats: args.stat || args.numstat,
+        patch: args.patch,
+        paths: args.path.clone(),
+        extensions: args.ext.clone(),
+        grep: args.grep.clone(),
+        invert_grep: args.invert_grep,
+        author: args.author.clone(),
+        exclude_authors: args.exclude_author.clone(),
+        date: args.date_kind(),
+        types: args.types.clone(),
+        trailers: args.trailer.clone(),
+        hide_reverted: args.hide_reverted,
+        dedup: args.dedup,
+        change_id: args.change_id,
+        signatures: args.show_signatures,
+        notes: args.notes.clone(),
+        releases: args.released,
+        first_parent: args.first_parent,
+        hide_merges: args.no_merges,
+        only_merges: args.merges,
+        abbrev: args.abbrev.flatten(),
+        strict: args.strict,
+    };
+    let collected = collect_commitsets(config, false, range, jobs, &options)?;
+    let mut commitsets = collected.commitsets;
+
+    if args.reverse {
+        commitsets.reverse();
+    }
+
+    // Only made now that there's a log to write, so that a run that fails
+    // doesn't leave an empty file behind
+    let mut out: Box<dyn Write + Send> = match &args.output {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).map_err(|e| {
+                GglError::IoError(format!("{}: {}", path.display(), e))
+            })?))
         }
+        None => Box::new(io::stdout()),
+    };
+    finish_output(print_log(&mut *out, &mut commitsets, format, args).and_then(|()| out.flush()))?;
+
+    for report in &failures {
+        if let Err(e) = &report.outcome {
+            warn!(repo = %report.name, error = %e, "fetching failed");
+        }
+    }
+    for failure in &collected.failures {
+        warn!(repo = %failure.name, error = %failure.error, "walking failed");
     }
 
-    let local_file = PathBuf::from("config.yaml");
-    if local_file.exists() {
-        return Ok(local_file);
+    if !collected.failures.is_empty() {
+        return Err(GglError::WalkFailed(collected.failures.len()));
+    }
+    if !failures.is_empty() {
+        return Err(GglError::FetchFailed(failures.len()));
+    }
+    if args.fail_if_empty && commitsets.iter().all(|set| set.commits.is_empty()) {
+        return Err(GglError::NoCommits);
     }
+    Ok(())
+}
+
+fn confirm(question: &str) -> Result<bool, GglError> {
+    let mut out = io::stdout();
+    finish_output(write!(out, "{} [Y/n] ", question).and_then(|()| out.flush()))?;
 
-    return Err(GglError::MissingConfigFile);
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer.is_empty() || answer == "y" || answer == "yes")
 }
 
-fn print_json(sets: &mut Vec<CommitSet>, reverse: bool) {
-    let mut commits: Vec<&GlobalCommit> = vec![];
+fn run_init(
+    config_path: Option<PathBuf>,
+    config_format: Option<ConfigFormat>,
+    args: &InitArgs,
+) -> Result<(), GglError> {
+    // Without --config, we write to the first place get_config_path looks
+    let config_path = match config_path {
+        Some(path) => path,
+        None => match dirs::config_dir() {
+            Some(dir) => dir.join("ggl.yaml"),
+            None => PathBuf::from("config.yaml"),
+        },
+    };
+
+    if config_path.exists() && !args.force {
+        return Err(GglError::ConfigFileExists(
+            config_path.display().to_string(),
+        ));
+    }
 
-    for set in sets {
-        if reverse {
-            set.commits.reverse();
+    let root = match &args.dir {
+        Some(dir) => dir.clone(),
+        None => env::current_dir()?,
+    };
+    let root = root.canonicalize()?;
+
+    let mut repositories = vec![];
+    for path in find_git_repositories(&root) {
+        let repo = git2::Repository::open(root.join(&path))?;
+        let name = match path.to_str() {
+            Some("") | None => continue,
+            Some(name) => name.to_string(),
+        };
+
+        let (remote, branch) = match detect_upstream(&repo) {
+            Some(upstream) => upstream,
+            None => {
+                warn!(repo = %name, "skipped: no remote branch found");
+                continue;
+            }
+        };
+
+        let url = repo
+            .find_remote(&remote)
+            .ok()
+            .and_then(|remote| remote.url().map(str::to_string));
+
+        let question = format!("Include {} ({}/{})?", name, remote, branch);
+        if !args.yes && !confirm(&question)? {
+            continue;
         }
-        for commit in &set.commits {
-            commits.push(commit);
+
+        repositories.push(Repository {
+            name: name.clone(),
+            path: name,
+            url,
+            remote: Some(remote),
+            branch: Some(branch),
+            fetch: true,
+            ..Default::default()
+        });
+    }
+
+    let config = Config {
+        include: vec![],
+        profiles: BTreeMap::new(),
+        teams: BTreeMap::new(),
+        filters: vec![],
+        author: vec![],
+        author_domains: vec![],
+        exclude_authors: vec![],
+        mailmap: None,
+        theme: Theme::default(),
+        date_format: None,
+        blocks: vec![Block {
+            name: None,
+            root: root.display().to_string(),
+            repositories,
+            discover: false,
+            ignore: vec![],
+            auth: None,
+            filters: vec![],
+            author: vec![],
+            author_domains: vec![],
+            exclude_authors: vec![],
+            mailmap: None,
+        }],
+    };
+
+    if let Some(dir) = config_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let format = config_format.unwrap_or_else(|| ConfigFormat::from_path(&config_path));
+    fs::write(&config_path, format.to_string(&config)?)?;
+    finish_output(writeln!(
+        io::stdout(),
+        "Wrote {} repositories to {}",
+        config.blocks[0].repositories.len(),
+        config_path.display()
+    ))
+}
+
+fn run_validate(config: &Config) -> Result<(), GglError> {
+    let problems = validate_config(config);
+    let mut out = io::stdout();
+    if problems.is_empty() {
+        return finish_output(writeln!(out, "Config is valid"));
+    }
+
+    finish_output(
+        problems
+            .iter()
+            .try_for_each(|problem| writeln!(out, "{}", problem)),
+    )?;
+    Err(GglError::InvalidConfig(problems.len()))
+}
+
+fn run_repos(config: &Config, args: &ReposArgs) -> Result<(), GglError> {
+    let mut repos = vec![];
+    for block in &config.blocks {
+        for r in &block.repositories {
+            repos.push(RepoInfo {
+                name: &r.name,
+                block: &block.name,
+                path: block.repository_path(r),
+                url: &r.url,
+                remote: &r.remote,
+                remotes: &r.remotes,
+                branch: &r.branch,
+                git_ref: &r.git_ref,
+                fetch: r.fetch,
+                prune: r.prune,
+                fetch_tags: r.fetch_tags,
+                filters: &r.filters,
+                tags: &r.tags,
+            });
+        }
+    }
+
+    finish_output(print_repos(&mut io::stdout(), &repos, args.json))
+}
+
+fn print_repos(out: &mut dyn Write, repos: &[RepoInfo], json: bool) -> io::Result<()> {
+    if json {
+        match serde_json::to_string(repos) {
+            Ok(c) => writeln!(out, "{}", c)?,
+            Err(e) => writeln!(out, "Error {:?}", e)?,
+        }
+        return Ok(());
+    }
+
+    for repo in repos {
+        writeln!(out, "{}", repo.name.yellow())?;
+        if let Some(block) = repo.block {
+            writeln!(out, "    Block:   {}", block)?;
+        }
+        writeln!(out, "    Path:    {}", repo.path.display())?;
+        if let Some(url) = repo.url {
+            writeln!(out, "    URL:     {}", url)?;
+        }
+        let remote = match repo.remote {
+            Some(remote) => Some(remote),
+            None => repo.remotes.first(),
+        };
+        match (remote, repo.branch) {
+            (Some(remote), Some(branch)) => writeln!(out, "    Branch:  {}/{}", remote, branch)?,
+            (Some(remote), None) => writeln!(out, "    Branch:  {} (default branch)", remote)?,
+            (None, Some(branch)) => writeln!(out, "    Branch:  {} (local)", branch)?,
+            (None, None) => writeln!(out, "    Branch:  HEAD (local)")?,
+        }
+        if let Some(git_ref) = repo.git_ref {
+            writeln!(out, "    Ref:     {}", git_ref)?;
+        }
+        if !repo.remotes.is_empty() {
+            writeln!(out, "    Remotes: {}", repo.remotes.join(", "))?;
+        }
+        let mut fetch = if repo.fetch { "yes" } else { "no" }.to_string();
+        if repo.fetch && repo.prune {
+            fetch.push_str(", prune");
+        }
+        if repo.fetch && repo.fetch_tags {
+            fetch.push_str(", all tags");
+        }
+        writeln!(out, "    Fetch:   {}", fetch)?;
+        if !repo.tags.is_empty() {
+            writeln!(out, "    Tags:    {}", repo.tags.join(", "))?;
+        }
+        for filter in repo.filters.iter().flatten() {
+            let match_type = match filter.match_type {
+                MatchType::Path => "",
+                MatchType::Regex => " (regex)",
+                MatchType::Prefix => " (prefix)",
+                MatchType::Extension => " (extension)",
+            };
+            writeln!(
+                out,
+                "    Filter:  {:?}{} {}",
+                filter.filter_type,
+                match_type,
+                filter.paths.join(", ")
+            )?;
         }
     }
 
-    match serde_json::to_string(&commits) {
-        Ok(c) => println!("{}", c),
-        Err(e) => println!("Errror {:?}", e),
+    Ok(())
+}
+
+fn print_plan(out: &mut dyn Write, config: &Config, fetch: bool) -> io::Result<()> {
+    for plan in plan(config, fetch) {
+        writeln!(out, "{}", plan.name.yellow())?;
+        writeln!(out, "    Open:    {}", plan.path.display())?;
+        if !plan.fetch.is_empty() {
+            writeln!(out, "    Fetch:   {}", plan.fetch.join(", "))?;
+        }
+        match plan.walk {
+            Ok(refs) if refs.is_empty() => writeln!(out, "    Walk:    no matching branches")?,
+            Ok(refs) => {
+                for (name, start) in refs {
+                    match start {
+                        Some(id) => writeln!(out, "    Walk:    {} ({:.7})", name, id)?,
+                        None => writeln!(out, "    Walk:    {} (missing)", name)?,
+                    }
+                }
+            }
+            Err(e) => writeln!(out, "    {}", format!("{}", e).red())?,
+        }
     }
+    Ok(())
 }
 
-fn run(args: &Args) -> Result<(), GglError> {
-    let config_path = get_config_path(args.config.clone())?;
-    let config = load_config(config_path)?;
-    let until = git2::Time::new(get_until(&args.until), 0);
-    let mut commitsets = collect_commitsets(&config, args.fetch, until)?;
+fn run_fetch(config: &Config, args: &FetchArgs, dry_run: bool) -> Result<(), GglError> {
+    if dry_run {
+        return finish_output(print_plan(&mut io::stdout(), config, true));
+    }
 
-    if args.reverse {
-        commitsets.reverse();
+    let reports = fetch_all(config, get_jobs(args.jobs));
+    finish_output(print_fetch_reports(&mut io::stdout(), &reports))?;
+
+    let failed = reports.iter().filter(|r| r.outcome.is_err()).count();
+    if failed > 0 {
+        return Err(GglError::FetchFailed(failed));
     }
+    Ok(())
+}
 
-    if args.json {
-        print_json(&mut commitsets, args.reverse);
-    } else {
-        for set in commitsets.iter_mut() {
-            print_commit_set(set, args.reverse);
+fn print_fetch_reports(out: &mut dyn Write, reports: &[FetchReport]) -> io::Result<()> {
+    writeln!(out)?;
+    for report in reports {
+        let summary = match &report.outcome {
+            Ok(FetchOutcome::Skipped) => "skipped".to_string(),
+            Ok(FetchOutcome::NewBranch) => "new branch".to_string(),
+            Ok(FetchOutcome::NewCommits(0)) => "up to date".to_string(),
+            Ok(FetchOutcome::NewCommits(1)) => "1 new commit".to_string(),
+            Ok(FetchOutcome::NewCommits(n)) => format!("{} new commits", n),
+            Err(e) => format!("{}", e).red().to_string(),
+        };
+        writeln!(out, "{:<20} {}", report.name, summary)?;
+    }
+    Ok(())
+}
+
+fn run_clone(config: &Config, args: &CloneArgs, dry_run: bool) -> Result<(), GglError> {
+    if dry_run {
+        return finish_output(print_clone_plan(&mut io::stdout(), config));
+    }
+
+    let reports = clone_missing(config, get_jobs(args.jobs));
+    finish_output(print_clone_reports(&mut io::stdout(), &reports))?;
+
+    let failed = reports.iter().filter(|r| r.outcome.is_err()).count();
+    if failed > 0 {
+        return Err(GglError::CloneFailed(failed));
+    }
+    Ok(())
+}
+
+fn print_clone_plan(out: &mut dyn Write, config: &Config) -> io::Result<()> {
+    for block in &config.blocks {
+        for r in &block.repositories {
+            let path = block.repository_path(r);
+            if let (false, Some(url)) = (path.exists(), &r.url) {
+                writeln!(
+                    out,
+                    "Would clone {} from {} into {}",
+                    r.name,
+                    url,
+                    path.display()
+                )?;
+            }
         }
     }
+    Ok(())
+}
 
+fn print_clone_reports(out: &mut dyn Write, reports: &[CloneReport]) -> io::Result<()> {
+    writeln!(out)?;
+    for report in reports {
+        let summary = match &report.outcome {
+            Ok(CloneOutcome::Exists) => "exists".to_string(),
+            Ok(CloneOutcome::NoUrl) => "missing, no url".yellow().to_string(),
+            Ok(CloneOutcome::Cloned) => "cloned".to_string(),
+            Err(e) => format!("{}", e).red().to_string(),
+        };
+        writeln!(out, "{:<20} {}", report.name, summary)?;
+    }
     Ok(())
 }
 
+fn run(args: &Args) -> Result<(), GglError> {
+    // init creates the config, and schema is always the same, so they're the
+    // commands that don't need it
+    if let Some(Command::Init(init_args)) = &args.command {
+        return run_init(args.config.first().cloned(), args.config_format, init_args);
+    }
+    if let Some(Command::Schema) = &args.command {
+        return finish_output(write!(io::stdout(), "{}", JSON_SCHEMA));
+    }
+
+    let config_paths = match args.config.len() {
+        0 => vec![get_config_path(None)?],
+        _ => args.config.clone(),
+    };
+
+    let mut config: Option<Config> = None;
+    for path in config_paths {
+        let path = get_config_path(Some(path))?;
+        let format = args
+            .config_format
+            .unwrap_or_else(|| ConfigFormat::from_path(&path));
+        let loaded = load_config_as(path, format)?;
+        match &mut config {
+            Some(config) => config.merge(loaded),
+            None => config = Some(loaded),
+        }
+    }
+    let mut config = config.unwrap();
+    if let Some(profile) = &args.profile {
+        config.select_profile(profile)?;
+    }
+    config.select_blocks(&args.block)?;
+    config.select_tags(&args.tag);
+    config.select_repositories(&args.repo)?;
+    config.set_fetch_defaults(args.fetch_timeout, args.fetch_retries);
+
+    match &args.command {
+        None | Some(Command::Log(_)) => run_log(&config, args.log().unwrap(), args.dry_run),
+        Some(Command::Validate) => run_validate(&config),
+        Some(Command::Repos(repos_args)) => run_repos(&config, repos_args),
+        Some(Command::Fetch(fetch_args)) => run_fetch(&config, fetch_args, args.dry_run),
+        Some(Command::Clone(clone_args)) => run_clone(&config, clone_args, args.dry_run),
+        Some(Command::Init(_)) | Some(Command::Schema) => unreachable!(),
+    }
+}
+
 fn main() {
     let args = Args::from_args();
+    // colored already looks at whether stdout is a terminal, NO_COLOR,
+    // CLICOLOR and CLICOLOR_FORCE
+    match args.color {
+        // Whatever stdout is, the file we write the log to isn't a terminal
+        ColorWhen::Auto if args.log().is_some_and(|log| log.output.is_some()) => {
+            colored::control::set_override(false)
+        }
+        ColorWhen::Auto => {}
+        ColorWhen::Always => colored::control::set_override(true),
+        ColorWhen::Never => colored::control::set_override(false),
+    }
+    let level = match args.verbose {
+        0 => tracing::Level::WARN,
+        1 => tracing::Level::INFO,
+        2 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    };
+    let log = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());
+    match args.log_format {
+        LogFormat::Json => log.json().init(),
+        // Warnings can do without the timestamps that -v needs
+        LogFormat::Text if args.verbose == 0 => log.without_time().with_target(false).init(),
+        LogFormat::Text => log.init(),
+    }
+    ggl::set_progress(match (args.quiet, args.log_format, args.verbose) {
+        (true, _, _) => Progress::Quiet,
+        (_, LogFormat::Json, _) => Progress::Log,
+        (_, LogFormat::Text, 0) => Progress::Auto,
+        // The log lines would get drawn over by bars
+        (_, LogFormat::Text, _) => Progress::Lines,
+    });
     match run(&args) {
         Ok(()) => {}
-        Err(e) => println!("error: {:?}", e),
+        Err(e) => {
+            match args.log_format {
+                LogFormat::Text => eprintln!("error: {}", e),
+                LogFormat::Json => tracing::error!(error = %e, "failed"),
+            }
+            process::exit(e.exit_code());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Duration;
+
+    #[test]
+    fn relative_dates_round_like_git() {
+        let now = time::macros::datetime!(2024-03-31 15:30 UTC);
+        let cases = [
+            (Duration::ZERO, "0 seconds ago"),
+            (Duration::seconds(1), "1 second ago"),
+            (Duration::seconds(89), "89 seconds ago"),
+            (Duration::seconds(90), "2 minutes ago"),
+            (Duration::minutes(89), "89 minutes ago"),
+            (Duration::minutes(90), "2 hours ago"),
+            (Duration::hours(35), "35 hours ago"),
+            (Duration::hours(36), "2 days ago"),
+            (Duration::days(13), "13 days ago"),
+            (Duration::days(14), "2 weeks ago"),
+            (Duration::days(69), "10 weeks ago"),
+            (Duration::days(70), "2 months ago"),
+            (Duration::days(400), "1 year, 1 month ago"),
+            (Duration::days(730), "2 years ago"),
+            (Duration::days(2000), "5 years ago"),
+            (Duration::seconds(-1), "in the future"),
+        ];
+        for (age, expected) in cases {
+            assert_eq!(relative_date(&(now - age), &now), expected, "{}", age);
+        }
     }
 }
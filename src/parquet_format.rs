@@ -0,0 +1,100 @@
+// ggl --- global git log
+// Copyright (C) 2022  Honza Pokorny <honza@pokorny.ca>
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `--format parquet`: the log as an Apache Parquet file, one row per commit,
+//! for loading into a data warehouse.
+//!
+//! The columns are part of ggl's interface, so they only ever get added to:
+//!
+//! | column  | type                       |
+//! |---------|----------------------------|
+//! | sha     | string                     |
+//! | repo    | string                     |
+//! | author  | string                     |
+//! | ts      | timestamp, µs, UTC         |
+//! | message | string                     |
+//! | files   | list of string             |
+
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{ArrayRef, RecordBatch, StringArray, TimestampMicrosecondArray};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use ggl::{GglError, GlobalCommit};
+use parquet::arrow::ArrowWriter;
+use std::io::Write;
+use std::sync::Arc;
+
+fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("sha", DataType::Utf8, false),
+        Field::new("repo", DataType::Utf8, false),
+        Field::new("author", DataType::Utf8, false),
+        Field::new(
+            "ts",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("message", DataType::Utf8, false),
+        Field::new(
+            "files",
+            DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+            false,
+        ),
+    ])
+}
+
+fn strings<'a>(commits: &[&'a GlobalCommit], f: impl Fn(&'a GlobalCommit) -> &'a str) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(
+        commits.iter().map(|commit| f(commit)),
+    ))
+}
+
+/// Write `commits` to `out` as a Parquet file.
+pub fn write<W: Write + Send>(commits: &[&GlobalCommit], out: W) -> Result<(), GglError> {
+    let error = |e: &dyn std::fmt::Display| GglError::IoError(format!("parquet: {}", e));
+
+    let ts = TimestampMicrosecondArray::from_iter_values(
+        commits
+            .iter()
+            .map(|commit| (commit.date.unix_timestamp_nanos() / 1000) as i64),
+    )
+    .with_timezone("UTC");
+    let mut files = ListBuilder::new(StringBuilder::new());
+    for commit in commits {
+        for file in &commit.files {
+            files.values().append_value(&file.path);
+        }
+        files.append(true);
+    }
+
+    let schema = Arc::new(schema());
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            strings(commits, |commit| &commit.sha),
+            strings(commits, |commit| &commit.repo_name),
+            strings(commits, |commit| &commit.author),
+            Arc::new(ts),
+            strings(commits, |commit| &commit.message),
+            Arc::new(files.finish()),
+        ],
+    )
+    .map_err(|e| error(&e))?;
+
+    let mut writer = ArrowWriter::try_new(out, schema, None).map_err(|e| error(&e))?;
+    writer.write(&batch).map_err(|e| error(&e))?;
+    writer.close().map_err(|e| error(&e))?;
+    Ok(())
+}
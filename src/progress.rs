@@ -0,0 +1,130 @@
+// ggl --- global git log
+// Copyright (C) 2022  Honza Pokorny <honza@pokorny.ca>
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Telling what we're up to while fetching, cloning and walking lots of
+//! repositories, on stderr, so that it doesn't get mixed up with the log.
+//!
+//! In a terminal, that's a bar counting the repositories, and a bar for each
+//! transfer that's underway.  Otherwise, e.g. in CI, it's a line for each
+//! repository as we get to it.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+
+/// How we tell what we're up to.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Progress {
+    /// Bars in a terminal, and lines otherwise
+    #[default]
+    Auto,
+    /// Lines, even in a terminal, e.g. so that the lines of a verbose log
+    /// don't have to make way for bars
+    Lines,
+    /// As info events of the log, e.g. when it's JSON
+    Log,
+    /// Not at all
+    Quiet,
+}
+
+static MODE: OnceLock<Progress> = OnceLock::new();
+
+static BARS: OnceLock<Option<MultiProgress>> = OnceLock::new();
+
+/// Choose how we tell what we're up to, before we start.
+pub fn set_progress(mode: Progress) {
+    let _ = MODE.set(mode);
+}
+
+fn mode() -> Progress {
+    *MODE.get_or_init(Progress::default)
+}
+
+// Where the bars go, if we draw any
+fn bars() -> Option<&'static MultiProgress> {
+    BARS.get_or_init(|| {
+        (mode() == Progress::Auto && io::stderr().is_terminal()).then(MultiProgress::new)
+    })
+    .as_ref()
+}
+
+/// Tell what we're up to, unless we were asked to be quiet.
+pub fn progress(message: fmt::Arguments) {
+    match (mode(), bars()) {
+        (Progress::Quiet, _) => {}
+        (Progress::Log, _) => tracing::info!("{}", message),
+        (_, Some(bars)) => bars.suspend(|| eprintln!("{}", message)),
+        (_, None) => eprintln!("{}", message),
+    }
+}
+
+/// Counts the repositories that are done, out of all of them.
+pub(crate) struct Counter(Option<ProgressBar>);
+
+impl Counter {
+    pub(crate) fn new(verb: &str, repositories: usize) -> Counter {
+        Counter(bars().map(|bars| {
+            let style = ProgressStyle::with_template(
+                "{prefix:>10.cyan.bold} [{bar:30}] {pos}/{len} repositories",
+            )
+            .unwrap()
+            .progress_chars("=> ");
+            let bar = ProgressBar::hidden()
+                .with_style(style)
+                .with_prefix(verb.to_string());
+            bar.set_length(repositories as u64);
+            bars.add(bar)
+        }))
+    }
+
+    pub(crate) fn inc(&self) {
+        if let Some(bar) = &self.0 {
+            bar.inc(1);
+        }
+    }
+}
+
+impl Drop for Counter {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.0 {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+/// A bar for fetching or cloning `what`, to follow along with in libgit2's
+/// transfer callback, or, without bars, a line saying we've started.
+pub(crate) fn transfer(verb: &str, what: &str) -> Option<ProgressBar> {
+    let bars = match bars() {
+        Some(bars) => bars,
+        None => {
+            progress(format_args!("{} {}", verb, what));
+            return None;
+        }
+    };
+    let style =
+        ProgressStyle::with_template("{prefix:>10.green} {msg} [{bar:20}] {pos}/{len} objects")
+            .unwrap()
+            .progress_chars("=> ");
+    let bar = ProgressBar::hidden()
+        .with_style(style)
+        .with_prefix(verb.to_string())
+        .with_message(what.to_string())
+        // Gone once the fetch is done with it
+        .with_finish(ProgressFinish::AndClear);
+    Some(bars.add(bar))
+}
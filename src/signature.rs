@@ -0,0 +1,165 @@
+// ggl --- global git log
+// Copyright (C) 2022  Honza Pokorny <honza@pokorny.ca>
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Verifying the GPG and SSH signatures of commits.
+//!
+//! libgit2 can only hand us the signature and the data it signs, so we leave
+//! the verification itself to the same programs git uses, configured the same
+//! way:
+//!
+//! 1.  GPG signatures are checked with `gpg.program`, `gpg` by default,
+//!     against the keys in the user's keyring
+//! 2.  SSH signatures are checked with `ssh-keygen` against
+//!     `gpg.ssh.allowedSignersFile`; without one, we can only tell that a
+//!     signature is broken, not that it's good
+
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Whether a commit's signature checks out.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureStatus {
+    /// Signed with a key we trust
+    Good,
+    /// The signature doesn't match the commit
+    Bad,
+    /// Signed, but we don't have the key, or the program, to check it
+    Unknown,
+    Unsigned,
+}
+
+impl SignatureStatus {
+    /// Check the signature of the commit `id` in `repo`.
+    pub fn of(repo: &git2::Repository, id: git2::Oid) -> SignatureStatus {
+        let (signature, data) = match repo.extract_signature(&id, None) {
+            Ok(extracted) => extracted,
+            Err(_) => return SignatureStatus::Unsigned,
+        };
+        let config = repo.config().ok();
+        let setting = |name: &str| config.as_ref().and_then(|c| c.get_path(name).ok());
+
+        // The same commit may be checked in more than one repository at once
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "ggl-{}-{}-{}.sig",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            id
+        );
+        let signature_path = std::env::temp_dir().join(name);
+        if fs::write(&signature_path, &*signature).is_err() {
+            return SignatureStatus::Unknown;
+        }
+        let status = if signature.starts_with(b"-----BEGIN SSH SIGNATURE-----") {
+            verify_ssh(
+                &signature_path,
+                &data,
+                setting("gpg.ssh.allowedSignersFile"),
+            )
+        } else {
+            let program = setting("gpg.program").unwrap_or_else(|| PathBuf::from("gpg"));
+            verify_gpg(&program, &signature_path, &data)
+        };
+        let _ = fs::remove_file(&signature_path);
+        status.unwrap_or(SignatureStatus::Unknown)
+    }
+}
+
+// Run `command` with `input` on its standard input, and return whether it
+// succeeded, and its standard output.
+fn run(command: &mut Command, input: &[u8]) -> Option<(bool, String)> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(input).ok()?;
+    let output = child.wait_with_output().ok()?;
+    Some((
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    ))
+}
+
+fn verify_gpg(program: &Path, signature: &Path, data: &[u8]) -> Option<SignatureStatus> {
+    let mut command = Command::new(program);
+    command
+        .args(["--status-fd=1", "--verify"])
+        .arg(signature)
+        .arg("-");
+    let (_, status) = run(&mut command, data)?;
+
+    let has = |keyword: &str| {
+        status
+            .lines()
+            .any(|line| line.split_whitespace().nth(1) == Some(keyword))
+    };
+    if has("BADSIG") {
+        Some(SignatureStatus::Bad)
+    } else if has("GOODSIG") && has("VALIDSIG") {
+        Some(SignatureStatus::Good)
+    } else {
+        Some(SignatureStatus::Unknown)
+    }
+}
+
+fn verify_ssh(signature: &Path, data: &[u8], allowed: Option<PathBuf>) -> Option<SignatureStatus> {
+    let allowed = match allowed {
+        Some(allowed) => allowed,
+        None => {
+            // All we can do without knowing whose keys to trust
+            let mut command = Command::new("ssh-keygen");
+            command
+                .args(["-Y", "check-novalidate", "-n", "git", "-s"])
+                .arg(signature);
+            let (valid, _) = run(&mut command, data)?;
+            return Some(match valid {
+                true => SignatureStatus::Unknown,
+                false => SignatureStatus::Bad,
+            });
+        }
+    };
+
+    let mut command = Command::new("ssh-keygen");
+    command
+        .args(["-Y", "find-principals", "-f"])
+        .arg(&allowed)
+        .arg("-s")
+        .arg(signature);
+    let (found, principals) = run(&mut command, &[])?;
+    let principal = match principals.lines().next() {
+        Some(principal) if found => principal.to_string(),
+        _ => return Some(SignatureStatus::Unknown),
+    };
+
+    let mut command = Command::new("ssh-keygen");
+    command
+        .args(["-Y", "verify", "-n", "git", "-f"])
+        .arg(&allowed)
+        .args(["-I", &principal, "-s"])
+        .arg(signature);
+    let (valid, _) = run(&mut command, data)?;
+    Some(match valid {
+        true => SignatureStatus::Good,
+        false => SignatureStatus::Bad,
+    })
+}
@@ -0,0 +1,159 @@
+// ggl --- global git log
+// Copyright (C) 2022  Honza Pokorny <honza@pokorny.ca>
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// A directory of its own for the test called `name`, with a config of a
+/// single repository that hasn't been cloned yet
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ggl-cli-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("ggl.yaml"),
+        format!(
+            "blocks:\n- root: {}\n  repositories:\n    - {{name: gone, path: gone, url: /nowhere}}\n",
+            dir.display()
+        ),
+    )
+    .unwrap();
+    dir
+}
+
+/// Run ggl with `args`, with its stdout a pipe that nobody reads any more
+fn ggl_into_closed_pipe(dir: &Path, args: &[&str]) -> Output {
+    let (reader, writer) = io::pipe().unwrap();
+    drop(reader);
+    Command::new(env!("CARGO_BIN_EXE_ggl"))
+        .arg("-c")
+        .arg(dir.join("ggl.yaml"))
+        .args(args)
+        .stdout(writer)
+        .stderr(Stdio::piped())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn closed_stdout_stops_quietly() {
+    let dir = scratch("closed-stdout");
+    let cases: [(&[&str], i32); 7] = [
+        (&["--dry-run", "--since", "2020-01-01"], 0),
+        (&["schema"], 0),
+        (&["repos"], 0),
+        (&["repos", "--json"], 0),
+        (&["--dry-run", "fetch"], 0),
+        (&["--dry-run", "clone"], 0),
+        // The repository is missing, which is still worth the exit status
+        (&["validate"], 1),
+    ];
+    for (args, status) in cases {
+        let output = ggl_into_closed_pipe(&dir, args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!stderr.contains("panicked"), "{:?}: {}", args, stderr);
+        assert_eq!(output.status.code(), Some(status), "{:?}: {}", args, stderr);
+    }
+}
+
+/// A config of one repository, `wk`, with a commit from a day ago on `main`
+fn with_repository(dir: &Path) {
+    let repo = git2::Repository::init(dir.join("wk")).unwrap();
+    let mut tree = repo.treebuilder(None).unwrap();
+    tree.insert("a", repo.blob(b"a").unwrap(), 0o100644)
+        .unwrap();
+    let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+    let day_ago = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+        - 24 * 60 * 60;
+    let signature =
+        git2::Signature::new("Test", "test@example.com", &git2::Time::new(day_ago, 0)).unwrap();
+    repo.commit(
+        Some("refs/heads/main"),
+        &signature,
+        &signature,
+        "feat: add a",
+        &tree,
+        &[],
+    )
+    .unwrap();
+    fs::write(
+        dir.join("ggl.yaml"),
+        format!(
+            "blocks:\n- root: {}\n  repositories:\n    - {{name: wk, path: wk, branch: main}}\n",
+            dir.display()
+        ),
+    )
+    .unwrap();
+}
+
+fn ggl(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ggl"))
+        .arg("-c")
+        .arg(dir.join("ggl.yaml"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn json_output_follows_the_schema() {
+    let dir = scratch("schema");
+    with_repository(&dir);
+    let schema: serde_json::Value = serde_json::from_str(ggl::JSON_SCHEMA).unwrap();
+    let validator = jsonschema::validator_for(&schema).unwrap();
+
+    for since in ["1 week ago", "now"] {
+        for args in [
+            &["--json"][..],
+            &["--json-sets"],
+            &["--json", "--group-by", "author"],
+        ] {
+            let output = ggl(&dir, &[args, &["--since", since]].concat());
+            assert!(output.status.success(), "{:?} since {}", args, since);
+            let log: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            // Nothing from now on, and a commit from a week on
+            assert_eq!(log.as_array().unwrap().is_empty(), since == "now");
+            if let Err(e) = validator.validate(&log) {
+                panic!("{:?} since {}: {}: {}", args, since, e, log);
+            }
+        }
+    }
+}
+
+#[test]
+fn p_shows_the_patch_and_capital_p_picks_a_profile() {
+    let dir = scratch("short-flags");
+    with_repository(&dir);
+    let mut config = fs::read_to_string(dir.join("ggl.yaml")).unwrap();
+    config.push_str("profiles: {mine: {repositories: [wk]}, theirs: {repositories: [other]}}\n");
+    fs::write(dir.join("ggl.yaml"), config).unwrap();
+
+    let stdout = |args: &[&str]| {
+        let output = ggl(&dir, &[args, &["--since", "1 week ago"]].concat());
+        assert!(output.status.success(), "{:?}", args);
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert!(stdout(&["-p"]).contains("diff --git a/a b/a"));
+    assert!(!stdout(&[]).contains("diff --git"));
+    assert!(stdout(&["-P", "mine"]).contains("feat: add a"));
+    assert!(!stdout(&["-P", "theirs"]).contains("feat: add a"));
+}